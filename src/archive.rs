@@ -0,0 +1,554 @@
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use tar::Archive;
+use xz2::read::XzDecoder;
+use zip::read::ZipArchive;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Compression {
+    Gzip,
+    Xz,
+    Bzip2,
+    Zstd,
+}
+
+impl Compression {
+    // Recognises compressed data by its first bytes, file names can't be trusted
+    pub fn from_magic(bytes: &[u8]) -> Option<Compression> {
+        if bytes.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if bytes.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Some(Compression::Xz)
+        } else if bytes.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else if bytes.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else {
+            None
+        }
+    }
+
+    // Content-Encoding values, as sent for pre-compressed files
+    pub fn from_encoding(value: &str) -> Option<Compression> {
+        match value.trim().to_lowercase().as_str() {
+            "gzip" | "x-gzip" => Some(Compression::Gzip),
+            "xz" | "x-xz" => Some(Compression::Xz),
+            "bzip2" | "x-bzip2" => Some(Compression::Bzip2),
+            "zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    pub fn from_content_type(value: &str) -> Option<Compression> {
+        let mime = value
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+        match mime.as_str() {
+            "application/gzip" | "application/x-gzip" => Some(Compression::Gzip),
+            "application/x-xz" => Some(Compression::Xz),
+            "application/x-bzip2" | "application/x-bzip" => Some(Compression::Bzip2),
+            "application/zstd" => Some(Compression::Zstd),
+            _ => None,
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gz",
+            Compression::Xz => "xz",
+            Compression::Bzip2 => "bz2",
+            Compression::Zstd => "zst",
+        }
+    }
+
+    // Multi stream decoders, so concatenated files like pigz or pbzip2 output come out whole
+    pub fn decoder<'a>(self, reader: impl Read + 'a) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+            Compression::Xz => Box::new(XzDecoder::new_multi_decoder(reader)),
+            Compression::Bzip2 => Box::new(MultiBzDecoder::new(reader)),
+            Compression::Zstd => Box::new(zstd::stream::read::Decoder::new(reader)?),
+        })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Format {
+    Zip,
+    Tar(Option<Compression>),
+    // A single compressed file, e.g. dump.sql.gz
+    Compressed(Compression),
+}
+
+// Tar headers carry "ustar" at offset 257
+fn is_tar(header: &[u8]) -> bool {
+    header.get(257..262) == Some(b"ustar")
+}
+
+fn read_head(reader: &mut impl Read, len: usize) -> io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(len);
+    reader.take(len as u64).read_to_end(&mut head)?;
+    Ok(head)
+}
+
+pub fn detect(path: &Path) -> io::Result<Option<Format>> {
+    let head = read_head(&mut File::open(path)?, 512)?;
+    if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+        return Ok(Some(Format::Zip));
+    }
+    if is_tar(&head) {
+        return Ok(Some(Format::Tar(None)));
+    }
+    let Some(compression) = Compression::from_magic(&head) else {
+        return Ok(None);
+    };
+    let mut decoder = compression.decoder(BufReader::new(File::open(path)?))?;
+    if is_tar(&read_head(&mut decoder, 512)?) {
+        Ok(Some(Format::Tar(Some(compression))))
+    } else {
+        Ok(Some(Format::Compressed(compression)))
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+// Caps on what one archive may unpack, so decompression bombs fail instead of filling the disk.
+// PLS_EXTRACT_LIMIT sets the size in GiB.
+struct Budget {
+    size: u64,
+    entries: usize,
+}
+
+impl Budget {
+    fn new() -> Budget {
+        let gib = std::env::var("PLS_EXTRACT_LIMIT")
+            .ok()
+            .and_then(|limit| limit.trim().parse::<u64>().ok())
+            .unwrap_or(64);
+        Budget {
+            size: gib.saturating_mul(1024 * 1024 * 1024),
+            entries: 1_000_000,
+        }
+    }
+
+    fn entry(&mut self) -> io::Result<()> {
+        if self.entries == 0 {
+            return Err(invalid("archive has too many entries".to_string()));
+        }
+        self.entries -= 1;
+        Ok(())
+    }
+
+    fn bytes(&mut self, len: u64) -> io::Result<()> {
+        if len > self.size {
+            return Err(invalid(
+                "archive unpacks to more than PLS_EXTRACT_LIMIT allows".to_string(),
+            ));
+        }
+        self.size -= len;
+        Ok(())
+    }
+
+    // Sizes in archive headers can lie, so count what actually comes out of the decoder
+    fn copy(&mut self, reader: &mut impl Read, writer: &mut impl Write) -> io::Result<()> {
+        let copied = io::copy(&mut reader.take(self.size.saturating_add(1)), writer)?;
+        self.bytes(copied)
+    }
+}
+
+// Drops the first `strip` components like tar --strip-components. Absolute paths and ..
+// are refused, entries that end up empty are skipped.
+fn entry_path(name: &Path, strip: usize) -> io::Result<Option<PathBuf>> {
+    let mut parts = Vec::new();
+    for component in name.components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            Component::CurDir => {}
+            _ => {
+                return Err(invalid(format!(
+                    "entry {} points outside the target directory",
+                    name.to_string_lossy()
+                )))
+            }
+        }
+    }
+    let path: PathBuf = parts.into_iter().skip(strip).collect();
+    Ok((!path.as_os_str().is_empty()).then_some(path))
+}
+
+// A symlink target, resolved from the real folder of the link, has to stay below the root.
+// Symlinks unpacked earlier are followed on the way, and .. is only allowed after a real
+// directory, a name that doesn't exist yet could still become a symlink later.
+fn check_link(root: &Path, path: &Path, target: &Path) -> io::Result<()> {
+    let outside = || {
+        invalid(format!(
+            "symlink {} -> {} points outside the target directory",
+            path.to_string_lossy(),
+            target.to_string_lossy()
+        ))
+    };
+    let mut current = fs::canonicalize(root.join(path).parent().unwrap_or(root))?;
+    for component in target.components() {
+        match component {
+            Component::Normal(part) => {
+                current.push(part);
+                if fs::symlink_metadata(&current).is_ok_and(|meta| meta.is_symlink()) {
+                    current = fs::canonicalize(&current).map_err(|_| outside())?;
+                }
+            }
+            Component::CurDir => {}
+            Component::ParentDir if current.is_dir() => {
+                current.pop();
+            }
+            _ => return Err(outside()),
+        }
+        if !current.starts_with(root) {
+            return Err(outside());
+        }
+    }
+    Ok(())
+}
+
+// Symlinks unpacked earlier must not carry later entries out of the root
+fn check_inside(root: &Path, path: &Path) -> io::Result<()> {
+    let mut current = root.to_path_buf();
+    for component in path.components() {
+        current.push(component);
+        let is_link = fs::symlink_metadata(&current).is_ok_and(|meta| meta.is_symlink());
+        if is_link && !fs::canonicalize(&current)?.starts_with(root) {
+            return Err(invalid(format!(
+                "entry {} goes through a symlink outside the target directory",
+                path.to_string_lossy()
+            )));
+        }
+    }
+    Ok(())
+}
+
+fn prepare_dir(root: &Path, path: &Path) -> io::Result<()> {
+    check_inside(root, path)?;
+    fs::create_dir_all(root.join(path))
+}
+
+// An existing symlink at the entry's own path is replaced rather than written through
+fn prepare_target(root: &Path, path: &Path) -> io::Result<PathBuf> {
+    if let Some(parent) = path.parent() {
+        prepare_dir(root, parent)?;
+    }
+    let target = root.join(path);
+    if fs::symlink_metadata(&target).is_ok_and(|meta| meta.is_symlink()) {
+        fs::remove_file(&target)?;
+    }
+    Ok(target)
+}
+
+fn extract_tar(
+    reader: impl Read,
+    root: &Path,
+    strip: usize,
+    budget: &mut Budget,
+) -> io::Result<()> {
+    let mut archive = Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        budget.entry()?;
+        let Some(path) = entry_path(&entry.path()?, strip)? else {
+            continue;
+        };
+        let kind = entry.header().entry_type();
+        if kind.is_dir() {
+            prepare_dir(root, &path)?;
+            continue;
+        }
+        let target = prepare_target(root, &path)?;
+        if kind.is_symlink() {
+            // A directory turned into a symlink would move what checked links point at
+            if fs::symlink_metadata(&target).is_ok_and(|meta| meta.is_dir()) {
+                return Err(invalid(format!(
+                    "symlink {} would replace a directory",
+                    path.to_string_lossy()
+                )));
+            }
+            let link = entry.link_name()?.unwrap_or_default();
+            check_link(root, &path, &link)?;
+        } else if kind.is_hard_link() {
+            // Hard links name another entry of the same archive, never a symlink or a path
+            // through one
+            let link = entry.link_name()?.unwrap_or_default();
+            if let Some(source) = entry_path(&link, strip)? {
+                check_inside(root, &source)?;
+                if fs::symlink_metadata(root.join(&source)).is_ok_and(|meta| meta.is_symlink()) {
+                    return Err(invalid(format!(
+                        "hard link {} points at the symlink {}",
+                        path.to_string_lossy(),
+                        source.to_string_lossy()
+                    )));
+                }
+                fs::hard_link(root.join(source), &target)?;
+            }
+            continue;
+        } else if kind.is_file() || kind.is_contiguous() || kind.is_gnu_sparse() {
+            budget.bytes(entry.size())?;
+        } else {
+            // Devices and fifos have no place in a download
+            continue;
+        }
+        entry.unpack(&target)?;
+    }
+    Ok(())
+}
+
+fn extract_zip(path: &Path, root: &Path, strip: usize, budget: &mut Budget) -> io::Result<()> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(path)?))?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        budget.entry()?;
+        let Some(path) = entry_path(Path::new(file.name()), strip)? else {
+            continue;
+        };
+        if file.is_dir() {
+            prepare_dir(root, &path)?;
+            continue;
+        }
+        // Symlink entries are written as plain files holding the link target
+        let target = prepare_target(root, &path)?;
+        budget.copy(&mut file, &mut File::create(&target)?)?;
+        #[cfg(unix)]
+        if let Some(mode) = file.unix_mode() {
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(&target, fs::Permissions::from_mode(mode & 0o777))?;
+        }
+    }
+    Ok(())
+}
+
+// The name of a file once decompressed, dump.sql.gz becomes dump.sql and a .tgz a .tar
+pub fn strip_extension(name: &str, compression: Compression) -> Option<String> {
+    let short = match compression {
+        Compression::Gzip => "tgz",
+        Compression::Xz => "txz",
+        Compression::Bzip2 => "tbz2",
+        Compression::Zstd => "tzst",
+    };
+    if let Some(stem) = name.strip_suffix(&format!(".{}", short)) {
+        return Some(format!("{}.tar", stem));
+    }
+    name.strip_suffix(&format!(".{}", compression.extension()))
+        .filter(|stem| !stem.is_empty())
+        .map(|stem| stem.to_string())
+}
+
+// Unpacks a tar stream as it arrives, with the same checks as extract()
+pub fn unpack_tar(reader: impl Read, dir: &Path, strip: usize) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let root = fs::canonicalize(dir)?;
+    extract_tar(reader, &root, strip, &mut Budget::new())
+}
+
+// A single compressed file keeps its name without the compression extension
+fn decompressed_name(path: &Path, compression: Compression) -> String {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    strip_extension(&name, compression).unwrap_or_else(|| format!("{}.out", name))
+}
+
+// Unpacks an archive into dir and returns its format. Every extraction goes through here,
+// user downloads as well as dependency installs. Without force a single compressed file
+// never replaces an existing one, like a download doesn't.
+pub fn extract(path: &Path, dir: &Path, strip: usize, force: bool) -> io::Result<Format> {
+    let Some(format) = detect(path)? else {
+        return Err(invalid(
+            "not a zip, tar, gzip, xz, bzip2 or zstd file".to_string(),
+        ));
+    };
+    fs::create_dir_all(dir)?;
+    let root = fs::canonicalize(dir)?;
+    let mut budget = Budget::new();
+    let file = || File::open(path).map(BufReader::new);
+    match format {
+        Format::Zip => extract_zip(path, &root, strip, &mut budget)?,
+        Format::Tar(None) => extract_tar(file()?, &root, strip, &mut budget)?,
+        Format::Tar(Some(compression)) => {
+            extract_tar(compression.decoder(file()?)?, &root, strip, &mut budget)?
+        }
+        Format::Compressed(compression) => {
+            let name = decompressed_name(path, compression);
+            if !force && fs::symlink_metadata(root.join(&name)).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists, pass -f/--force to replace it", name),
+                ));
+            }
+            let target = prepare_target(&root, Path::new(&name))?;
+            let copied = budget.copy(
+                &mut compression.decoder(file()?)?,
+                &mut File::create(&target)?,
+            );
+            if copied.is_err() {
+                fs::remove_file(&target)?;
+            }
+            copied?;
+        }
+    }
+    Ok(format)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+    use tar::{Builder, EntryType, Header};
+    use tempfile::tempdir;
+
+    enum Entry<'a> {
+        Dir(&'a str),
+        File(&'a str, &'a [u8]),
+        Symlink(&'a str, &'a str),
+        HardLink(&'a str, &'a str),
+    }
+
+    fn tarball(entries: &[Entry]) -> Vec<u8> {
+        let mut builder = Builder::new(Vec::new());
+        for entry in entries {
+            let mut header = Header::new_gnu();
+            header.set_mode(0o755);
+            header.set_size(0);
+            match entry {
+                Entry::Dir(path) => {
+                    header.set_entry_type(EntryType::Directory);
+                    builder.append_data(&mut header, path, io::empty()).unwrap();
+                }
+                Entry::File(path, data) => {
+                    header.set_size(data.len() as u64);
+                    builder.append_data(&mut header, path, *data).unwrap();
+                }
+                Entry::Symlink(path, target) => {
+                    header.set_entry_type(EntryType::Symlink);
+                    builder.append_link(&mut header, path, target).unwrap();
+                }
+                Entry::HardLink(path, target) => {
+                    header.set_entry_type(EntryType::Link);
+                    builder.append_link(&mut header, path, target).unwrap();
+                }
+            }
+        }
+        builder.into_inner().unwrap()
+    }
+
+    // Unpacks into top/out next to top/secret.txt, which no entry may reach
+    fn unpack(entries: &[Entry]) -> (tempfile::TempDir, io::Result<()>) {
+        let top = tempdir().unwrap();
+        fs::write(top.path().join("secret.txt"), b"secret").unwrap();
+        let result = unpack_tar(&tarball(entries)[..], &top.path().join("out"), 0);
+        (top, result)
+    }
+
+    fn secret_untouched(top: &Path) {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let meta = fs::metadata(top.join("secret.txt")).unwrap();
+            assert_eq!(meta.nlink(), 1);
+        }
+        assert_eq!(fs::read(top.join("secret.txt")).unwrap(), b"secret");
+    }
+
+    // Windows needs extra privileges for symlinks
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_inside_are_unpacked() {
+        let (top, result) = unpack(&[
+            Entry::Dir("dir"),
+            Entry::File("dir/file", b"data"),
+            Entry::Symlink("lib", "dir"),
+            Entry::Symlink("a", "lib/file"),
+            Entry::Symlink("dir/up", "../dir/file"),
+            Entry::HardLink("copy", "dir/file"),
+        ]);
+        result.unwrap();
+        let out = top.path().join("out");
+        assert_eq!(fs::read(out.join("a")).unwrap(), b"data");
+        assert_eq!(fs::read(out.join("dir/up")).unwrap(), b"data");
+        assert_eq!(fs::read(out.join("copy")).unwrap(), b"data");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_through_symlink_is_refused() {
+        let (top, result) = unpack(&[
+            Entry::Symlink("l", "."),
+            Entry::Symlink("m", "l/.."),
+            Entry::HardLink("h", "m/secret.txt"),
+        ]);
+        assert!(result.is_err());
+        assert!(!top.path().join("out/m").exists());
+        assert!(!top.path().join("out/h").exists());
+        secret_untouched(top.path());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_before_its_folder_link_is_refused() {
+        let (top, result) = unpack(&[Entry::Symlink("m", "l/.."), Entry::Symlink("l", ".")]);
+        assert!(result.is_err());
+        assert!(!top.path().join("out/m").exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_over_a_directory_is_refused() {
+        let (_top, result) = unpack(&[
+            Entry::Dir("l"),
+            Entry::Symlink("m", "l/.."),
+            Entry::Symlink("l", "."),
+        ]);
+        assert!(result.is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hard_link_to_a_symlink_is_refused() {
+        let (top, result) = unpack(&[
+            Entry::File("file", b"data"),
+            Entry::Symlink("s", "file"),
+            Entry::HardLink("h", "s"),
+        ]);
+        assert!(result.is_err());
+        assert!(!top.path().join("out/h").exists());
+    }
+
+    #[test]
+    fn hard_link_outside_is_refused() {
+        let (top, result) = unpack(&[Entry::HardLink("h", "../secret.txt")]);
+        assert!(result.is_err());
+        secret_untouched(top.path());
+    }
+
+    #[test]
+    fn compressed_file_is_not_replaced_without_force() {
+        let dir = tempdir().unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"new").unwrap();
+        let archive = dir.path().join("dump.sql.gz");
+        fs::write(&archive, encoder.finish().unwrap()).unwrap();
+        fs::write(dir.path().join("dump.sql"), b"old").unwrap();
+
+        let error = extract(&archive, dir.path(), 0, false).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(dir.path().join("dump.sql")).unwrap(), b"old");
+
+        extract(&archive, dir.path(), 0, true).unwrap();
+        assert_eq!(fs::read(dir.path().join("dump.sql")).unwrap(), b"new");
+    }
+}
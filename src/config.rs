@@ -0,0 +1,186 @@
+use crate::deps::DepsMode;
+use crate::paths::config_dir;
+use crate::theme::ThemeFile;
+use dirs::home_dir;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use toml::{Table, Value};
+
+// Defaults from config.toml, the command line overrides every one of them
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    // Where downloads go when no OUTPUT is given
+    pub output: Option<String>,
+    pub force: Option<bool>,
+    pub retries: Option<u32>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    pub proxy: Option<String>,
+    // best, worst, audio or a height like 720 for media downloads
+    pub quality: Option<String>,
+    pub deps: Option<DepsMode>,
+    #[serde(default)]
+    pub theme: ThemeFile,
+}
+
+fn config_path() -> Option<PathBuf> {
+    config_dir().map(|config| config.join("config.toml"))
+}
+
+// Tables are merged key by key, so a profile can change a single header or theme colour
+fn merge(base: &mut Table, overlay: Table) {
+    for (key, value) in overlay {
+        match value {
+            Value::Table(table) if base.get(&key).is_some_and(Value::is_table) => {
+                if let Some(Value::Table(inner)) = base.get_mut(&key) {
+                    merge(inner, table);
+                }
+            }
+            value => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+pub fn load(profile: Option<&str>) -> Result<Config, String> {
+    match config_path().filter(|path| path.is_file()) {
+        Some(path) => {
+            let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+            parse(&text, &path.to_string_lossy(), profile)
+        }
+        None => parse("", "config.toml", profile),
+    }
+}
+
+// The top level holds the defaults, a [profile.NAME] table picked with --profile goes on top.
+// name is the file errors are reported for.
+fn parse(text: &str, name: &str, profile: Option<&str>) -> Result<Config, String> {
+    let mut table: Table = text
+        .parse()
+        .map_err(|e: toml::de::Error| format!("{}: {}", name, e.message()))?;
+
+    let profiles = match table.remove("profile") {
+        Some(Value::Table(profiles)) => profiles,
+        Some(_) => return Err(format!("{}: profile must be a table", name)),
+        None => Table::new(),
+    };
+    if let Some(profile) = profile {
+        match profiles.get(profile) {
+            Some(Value::Table(overlay)) => merge(&mut table, overlay.clone()),
+            Some(_) => return Err(format!("{}: profile.{} must be a table", name, profile)),
+            None if profiles.is_empty() => {
+                return Err(format!(
+                    "Unknown profile {}, {} has no profiles",
+                    profile, name
+                ))
+            }
+            None => {
+                let known: Vec<&str> = profiles.keys().map(String::as_str).collect();
+                return Err(format!(
+                    "Unknown profile {}, the profiles are {}",
+                    profile,
+                    known.join(", ")
+                ));
+            }
+        }
+    }
+
+    Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| format!("{}: {}", name, e.message()))
+}
+
+impl Config {
+    // ~ isn't expanded by a shell in the config file, so it's done here
+    pub fn output_dir(&self) -> Option<String> {
+        let dir = self.output.as_deref()?;
+        match (dir.strip_prefix('~'), home_dir()) {
+            (Some(rest), Some(home)) => Some(
+                home.join(rest.trim_start_matches(['/', '\\']))
+                    .to_string_lossy()
+                    .to_string(),
+            ),
+            _ => Some(dir.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = r#"
+retries = 2
+quality = "720"
+
+[headers]
+Accept = "*/*"
+Referer = "https://example.com"
+
+[profile.work]
+proxy = "http://proxy:3128"
+
+[profile.work.headers]
+Referer = "https://intranet.example"
+"#;
+
+    #[test]
+    fn tables_merge_key_by_key() {
+        let mut base: Table = "a = 1\n[t]\nx = 1\ny = 1".parse().unwrap();
+        let overlay: Table = "b = 2\n[t]\ny = 2\nz = 2".parse().unwrap();
+        merge(&mut base, overlay);
+        let expected: Table = "a = 1\nb = 2\n[t]\nx = 1\ny = 2\nz = 2".parse().unwrap();
+        assert_eq!(base, expected);
+
+        // A table only replaces a plain value, the two aren't merged
+        let mut base: Table = "t = 1".parse().unwrap();
+        merge(&mut base, "[t]\nx = 1".parse().unwrap());
+        assert!(base["t"].is_table());
+    }
+
+    #[test]
+    fn profile_goes_on_top_of_the_defaults() {
+        let config = parse(TEXT, "config.toml", None).unwrap();
+        assert_eq!(config.retries, Some(2));
+        assert_eq!(config.proxy, None);
+        assert_eq!(config.headers["Referer"], "https://example.com");
+
+        let config = parse(TEXT, "config.toml", Some("work")).unwrap();
+        assert_eq!(config.retries, Some(2));
+        assert_eq!(config.quality.as_deref(), Some("720"));
+        assert_eq!(config.proxy.as_deref(), Some("http://proxy:3128"));
+        assert_eq!(config.headers["Accept"], "*/*");
+        assert_eq!(config.headers["Referer"], "https://intranet.example");
+    }
+
+    fn error(text: &str, profile: Option<&str>) -> String {
+        parse(text, "config.toml", profile)
+            .err()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn bad_profiles_are_reported() {
+        assert_eq!(
+            error(TEXT, Some("home")),
+            "Unknown profile home, the profiles are work"
+        );
+        assert_eq!(
+            error("retries = 1", Some("home")),
+            "Unknown profile home, config.toml has no profiles"
+        );
+        assert_eq!(
+            error("profile = 1", None),
+            "config.toml: profile must be a table"
+        );
+        assert_eq!(
+            error("[profile]\nwork = 1", Some("work")),
+            "config.toml: profile.work must be a table"
+        );
+        assert!(error("[profile.work]\nretry = 1", Some("work")).contains("unknown field"));
+    }
+}
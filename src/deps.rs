@@ -0,0 +1,1499 @@
+use crate::archive;
+use crate::go;
+use crate::manifest::{self, Platform, Sources};
+use crate::output;
+use crate::paths::{cache_dir, data_dir};
+use colored::*;
+use dirs::home_dir;
+use indicatif::{HumanBytes, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use reqwest::blocking::Client;
+use reqwest::header::USER_AGENT;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::error::Error;
+#[cfg(unix)]
+use std::fs::Permissions;
+use std::fs::{self, File, TryLockError};
+use std::io::{self, IsTerminal, Write};
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tempfile::TempDir;
+
+// Oldest releases pls is known to work with, older system copies only get a warning
+const YTDLP_MIN_VERSION: &str = "2024.04.09";
+const FFMPEG_MIN_VERSION: &str = "5.0";
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum DepsMode {
+    Bundled,
+    System,
+    Auto,
+}
+
+impl DepsMode {
+    pub fn parse(mode: &str) -> Option<DepsMode> {
+        match mode {
+            "bundled" => Some(DepsMode::Bundled),
+            "system" => Some(DepsMode::System),
+            "auto" => Some(DepsMode::Auto),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Tool {
+    Ytdlp,
+    Ffmpeg,
+}
+
+impl Tool {
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Ytdlp => "yt-dlp",
+            Tool::Ffmpeg => "ffmpeg",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Tool> {
+        match name {
+            "yt-dlp" => Some(Tool::Ytdlp),
+            "ffmpeg" => Some(Tool::Ffmpeg),
+            _ => None,
+        }
+    }
+
+    fn bin_name(self) -> String {
+        format!("{}{}", self.name(), std::env::consts::EXE_SUFFIX)
+    }
+
+    // Everything an install puts into libs, Termux builds of yt-dlp bring their own python
+    fn files(self) -> Vec<String> {
+        let exe = std::env::consts::EXE_SUFFIX;
+        match self {
+            Tool::Ytdlp => vec![
+                self.bin_name(),
+                "python3.12".to_string(),
+                "yt_dlp".to_string(),
+                "yt-dlp.receipt".to_string(),
+            ],
+            Tool::Ffmpeg => vec![
+                self.bin_name(),
+                format!("ffprobe{}", exe),
+                format!("ffplay{}", exe),
+                "ffmpeg.receipt".to_string(),
+            ],
+        }
+    }
+
+    fn env_var(self) -> &'static str {
+        match self {
+            Tool::Ytdlp => "PLS_YTDLP",
+            Tool::Ffmpeg => "PLS_FFMPEG",
+        }
+    }
+
+    fn min_version(self) -> &'static str {
+        match self {
+            Tool::Ytdlp => YTDLP_MIN_VERSION,
+            Tool::Ffmpeg => FFMPEG_MIN_VERSION,
+        }
+    }
+
+    // yt-dlp prints a bare date like 2024.12.13, ffmpeg prints "ffmpeg version 7.1 Copyright ..."
+    fn version(self, bin: &Path) -> Option<String> {
+        let arg = match self {
+            Tool::Ytdlp => "--version",
+            Tool::Ffmpeg => "-version",
+        };
+        let output = Command::new(bin).arg(arg).output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let line = stdout.lines().next()?.trim();
+        match self {
+            Tool::Ytdlp => Some(line.to_string()),
+            Tool::Ffmpeg => line.split_whitespace().nth(2).map(|v| v.to_string()),
+        }
+    }
+}
+
+// Paths yt-dlp downloads are run with
+pub struct MediaDeps {
+    pub ytdlp: PathBuf,
+    pub ffmpeg: PathBuf,
+}
+
+pub fn libs_dir() -> Option<PathBuf> {
+    data_dir().map(|data| data.join("libs"))
+}
+
+pub fn find_in_path(name: &str) -> Option<PathBuf> {
+    let file_name = format!("{}{}", name, std::env::consts::EXE_SUFFIX);
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(&file_name))
+        .find(|path| path.is_file())
+}
+
+fn version_numbers(version: &str) -> Vec<u32> {
+    version
+        .split(['.', '-'])
+        .map_while(|part| {
+            let digits: String = part.chars().take_while(|c| c.is_ascii_digit()).collect();
+            digits.parse().ok()
+        })
+        .collect()
+}
+
+// Git builds of ffmpeg report versions like N-118000-g1234, those are never considered old
+pub fn is_too_old(version: &str, min_version: &str) -> bool {
+    let numbers = version_numbers(version);
+    !numbers.is_empty() && numbers < version_numbers(min_version)
+}
+
+// Looks for a yt-dlp/ffmpeg outside of the bundle, None means the bundled copy should be used
+fn external_tool(tool: Tool, mode: DepsMode) -> Result<Option<PathBuf>, String> {
+    let explicit = std::env::var_os(tool.env_var()).map(PathBuf::from);
+    if let Some(path) = &explicit {
+        if !path.is_file() {
+            return Err(format!(
+                "{} points to {}, which doesn't exist!",
+                tool.env_var(),
+                path.to_string_lossy()
+            ));
+        }
+    } else if mode == DepsMode::Bundled {
+        return Ok(None);
+    }
+
+    let path = match explicit.or_else(|| find_in_path(tool.name())) {
+        Some(path) => path,
+        None if mode == DepsMode::System => {
+            return Err(format!("{} wasn't found in PATH!", tool.name()));
+        }
+        None => return Ok(None),
+    };
+
+    let version = tool.version(&path);
+    let usable = version
+        .as_deref()
+        .is_some_and(|version| !is_too_old(version, tool.min_version()));
+    if !usable && !output::quiet() {
+        println!(
+            "{} {} {} {} {} {}",
+            "Warning:".yellow().bold(),
+            tool.name().blue().bold(),
+            version.as_deref().unwrap_or("of unknown version").white(),
+            "at".white(),
+            path.to_string_lossy().white().bold(),
+            format!("is too old, pls needs {} or newer.", tool.min_version()).white()
+        );
+    }
+    // Explicit paths and system mode are respected, auto mode rather uses the bundle
+    if !usable && mode == DepsMode::Auto && std::env::var_os(tool.env_var()).is_none() {
+        return Ok(None);
+    }
+    Ok(Some(path))
+}
+
+pub fn resolve(mode: DepsMode) -> io::Result<Option<MediaDeps>> {
+    let Some(libs) = libs_dir() else {
+        println!(
+            "{} {} {}",
+            "Error:".red().bold(),
+            "Failed".white().bold(),
+            "to determine home directory.".white()
+        );
+        return Ok(None);
+    };
+
+    let mut ytdlp = None;
+    let mut ffmpeg = None;
+    let mut bundled = Vec::new();
+    for tool in [Tool::Ytdlp, Tool::Ffmpeg] {
+        match external_tool(tool, mode) {
+            Ok(Some(path)) if tool == Tool::Ytdlp => ytdlp = Some(path),
+            Ok(Some(path)) => ffmpeg = Some(path),
+            Ok(None) => bundled.push(tool),
+            Err(e) => {
+                println!("{} {}", "Error:".red().bold(), e.white());
+                return Ok(None);
+            }
+        }
+    }
+    if !bundled.is_empty() {
+        ytdlp_check(false, &bundled)?;
+        // ytdlp_check() has already reported why a tool could not be installed
+        if bundled
+            .iter()
+            .any(|tool| !libs.join(tool.bin_name()).exists())
+        {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(MediaDeps {
+        ytdlp: ytdlp.unwrap_or_else(|| libs.join(Tool::Ytdlp.bin_name())),
+        ffmpeg: ffmpeg.unwrap_or(libs),
+    }))
+}
+
+// Install progress is left out with -q, only failures are reported then
+fn announce(name: &str, update: bool) {
+    if output::quiet() {
+        return;
+    }
+    let action = if update { "Updating" } else { "Installing" };
+    println!("{} {}", action.white(), name.blue().bold());
+}
+
+fn install_result(name: &str, update: bool, success: bool) {
+    let action = if update { "update" } else { "install" };
+    if success && output::quiet() {
+        return;
+    }
+    if success {
+        println!(
+            "{} {} {}",
+            name.blue().bold(),
+            "has been successfully".white(),
+            format!("{}ed.", action.trim_end_matches('e'))
+                .white()
+                .bold()
+        );
+    } else {
+        println!(
+            "{} {} {} {}",
+            "Error:".red().bold(),
+            "Failed".white().bold(),
+            format!("to {}", action).white(),
+            name.blue().bold()
+        );
+    }
+}
+
+fn file_sha256(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+// Manifests list "<digest>  <file name>" per line, single file ones may contain just the digest
+fn parse_checksums(manifest: &str, file_name: &str) -> Option<String> {
+    let mut lines = manifest.lines().filter(|line| !line.trim().is_empty());
+    let listed = manifest.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let digest = parts.next()?;
+        let name = parts.next()?.trim_start_matches('*');
+        (name == file_name).then(|| digest.to_lowercase())
+    });
+    listed.or_else(|| {
+        let only = lines.next()?.trim();
+        if lines.next().is_none() && !only.contains(char::is_whitespace) {
+            Some(only.to_lowercase())
+        } else {
+            None
+        }
+    })
+}
+
+fn expected_sha256(checksums_url: &str, file_name: &str) -> Result<String, Box<dyn Error>> {
+    let manifest = match local_path(checksums_url) {
+        Some(path) => fs::read_to_string(path)?,
+        None => Client::new()
+            .get(checksums_url)
+            .send()?
+            .error_for_status()?
+            .text()?,
+    };
+    parse_checksums(&manifest, file_name)
+        .ok_or_else(|| format!("{} isn't listed in {}", file_name, checksums_url).into())
+}
+
+// Checks a downloaded artifact against the publisher's checksum manifest and returns its digest,
+// anything that doesn't match is deleted and None is returned
+pub fn verify_download(
+    url: &str,
+    path: &Path,
+    checksums_url: Option<&str>,
+) -> io::Result<Option<String>> {
+    if !path.exists() {
+        // fetch_artifact() has already reported why the download failed
+        return Ok(None);
+    }
+    let file_name = url.rsplit(['/', '\\']).next().unwrap_or(url);
+    let sha256 = file_sha256(path)?;
+    let Some(checksums_url) = checksums_url else {
+        if output::quiet() {
+            return Ok(Some(sha256));
+        }
+        println!(
+            "{} {} {}",
+            "Warning:".yellow().bold(),
+            file_name.white().bold(),
+            "has no published checksums, skipping verification.".white()
+        );
+        return Ok(Some(sha256));
+    };
+
+    match expected_sha256(checksums_url, file_name) {
+        Ok(expected) if expected == sha256 => {
+            if !output::quiet() {
+                println!(
+                    "{} {} {}",
+                    "Verified".white(),
+                    file_name.blue().bold(),
+                    format!("(sha256 {})", sha256).white()
+                );
+            }
+            Ok(Some(sha256))
+        }
+        Ok(expected) => {
+            fs::remove_file(path)?;
+            println!(
+                "{} {} {} {} {} {}",
+                "Error:".red().bold(),
+                "Checksum mismatch for".white(),
+                file_name.white().bold(),
+                format!("- expected {}, got {}.", expected, sha256).white(),
+                "Refusing to install".white(),
+                file_name.white().bold()
+            );
+            Ok(None)
+        }
+        Err(e) => {
+            fs::remove_file(path)?;
+            println!(
+                "{} {} {}",
+                "Error:".red().bold(),
+                "Failed to fetch checksums:".white(),
+                e.to_string().white()
+            );
+            Ok(None)
+        }
+    }
+}
+
+// Records where an installed tool came from and its digest for `pls deps status`
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or_default()
+}
+
+fn write_receipt(
+    dir: &Path,
+    tool: Tool,
+    source: &str,
+    sha256: &str,
+    verified: bool,
+    version: &str,
+) -> io::Result<()> {
+    fs::write(
+        dir.join(format!("{}.receipt", tool.name())),
+        format!(
+            "source={}\nsha256={}\nverified={}\ninstalled={}\nversion={}\n",
+            source,
+            sha256,
+            verified,
+            now_secs(),
+            version
+        ),
+    )
+}
+
+fn read_receipt(libs: &Path, tool: Tool) -> Vec<(String, String)> {
+    fs::read_to_string(libs.join(format!("{}.receipt", tool.name())))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect()
+}
+
+fn receipt_value<'a>(receipt: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    receipt
+        .iter()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value.as_str())
+}
+
+fn read_pin(libs: &Path) -> Option<String> {
+    fs::read_to_string(libs.join("yt-dlp.pin"))
+        .ok()
+        .map(|pin| pin.trim().to_string())
+        .filter(|pin| !pin.is_empty())
+}
+
+// Fills {release} in release URLs, a pinned yt-dlp is fetched from its exact tag instead of the latest one
+fn ytdlp_release(libs: &Path) -> String {
+    match read_pin(libs) {
+        Some(tag) => format!("download/{}", tag),
+        None => "latest/download".to_string(),
+    }
+}
+
+// Download URL and checksum manifest of a tool for this machine, taken from the deps manifest
+fn tool_source(
+    sources: &Sources,
+    tool: Tool,
+    platform: &Platform,
+    libs: &Path,
+) -> Option<(String, Option<String>)> {
+    let Some(artifact) = sources.find(tool.name(), platform) else {
+        println!(
+            "{} {} {} {}",
+            "Error:".red().bold(),
+            "No bundled".white(),
+            tool.name().blue().bold(),
+            format!("build is available for {}.", platform.describe()).white()
+        );
+        println!(
+            "{} {} {}",
+            "Install".white(),
+            tool.name().blue().bold(),
+            "with your package manager and run pls with --deps system, or list a build in the deps manifest."
+                .white()
+        );
+        return None;
+    };
+    if let Some(pin) = read_pin(libs).filter(|_| tool == Tool::Ytdlp && !artifact.pinnable()) {
+        println!(
+            "{} {} {} {}",
+            "Error:".red().bold(),
+            "yt-dlp is pinned to".white(),
+            pin.white().bold(),
+            "but its source has no {release} to pick that tag from, e.g. with a mirror - run pls deps pin latest or list a pinned build in the deps manifest."
+                .white()
+        );
+        return None;
+    }
+    let release = ytdlp_release(libs);
+    Some((
+        artifact.url.replace("{release}", &release),
+        artifact
+            .checksums
+            .as_ref()
+            .map(|checksums| checksums.replace("{release}", &release)),
+    ))
+}
+
+fn load_sources() -> Option<Sources> {
+    match manifest::load() {
+        Ok(sources) => Some(sources),
+        Err(e) => {
+            println!(
+                "{} {} {}",
+                "Error:".red().bold(),
+                "Failed to read the deps manifest:".white(),
+                e.white()
+            );
+            None
+        }
+    }
+}
+
+// Mirrors may point at local directories, those artifacts are copied instead of downloaded
+pub fn local_path(location: &str) -> Option<PathBuf> {
+    if let Some(path) = location.strip_prefix("file://") {
+        Some(PathBuf::from(path))
+    } else if !location.contains("://") {
+        Some(PathBuf::from(location))
+    } else {
+        None
+    }
+}
+
+pub fn fetch_artifact(url: &str, dest: &Path) -> io::Result<()> {
+    match local_path(url) {
+        Some(source) => {
+            if let Err(e) = fs::copy(&source, dest) {
+                println!(
+                    "{} {} {}",
+                    "Error".red().bold(),
+                    source.to_string_lossy().white().bold(),
+                    e.to_string().white()
+                );
+            }
+            Ok(())
+        }
+        None => go(url.to_string(), dest.to_string_lossy().to_string()),
+    }
+}
+
+// Updates leave a pinned yt-dlp alone, it's only reinstalled when the pin changes
+fn pinned_and_current(libs: &Path, ytdlp_url: &str) -> bool {
+    let Some(pin) = read_pin(libs) else {
+        return false;
+    };
+    let current = receipt_value(&read_receipt(libs, Tool::Ytdlp), "source") == Some(ytdlp_url);
+    if current {
+        println!(
+            "{} {} {} {}",
+            "yt-dlp".blue().bold(),
+            "is pinned to".white(),
+            pin.white().bold(),
+            "- run pls deps pin latest to follow new releases.".white()
+        );
+    }
+    current
+}
+
+// Fresh scratch directory inside libs, a new version is assembled here before it replaces the old one
+fn staging_dir(libs: &Path) -> io::Result<PathBuf> {
+    let staging = libs.join(".staging");
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+    fs::create_dir_all(&staging)?;
+    Ok(staging)
+}
+
+// Only one pls at a time may change libs, the lock is released when the returned file is dropped
+fn lock_libs(libs: &Path) -> io::Result<File> {
+    fs::create_dir_all(libs)?;
+    let lock = File::create(libs.join(".lock"))?;
+    match lock.try_lock() {
+        Ok(()) => return Ok(lock),
+        Err(TryLockError::WouldBlock) => {}
+        Err(TryLockError::Error(e)) => return Err(e),
+    }
+
+    let spinner = ProgressBar::new_spinner();
+    if output::quiet() {
+        spinner.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    spinner.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.blue} {msg}")
+            .unwrap(),
+    );
+    spinner.set_message(
+        "Waiting for another pls to finish installing"
+            .white()
+            .to_string(),
+    );
+    spinner.enable_steady_tick(Duration::from_millis(100));
+    let locked = lock.lock();
+    spinner.finish_and_clear();
+    locked?;
+    Ok(lock)
+}
+
+// Downloads and archives are unpacked in a fresh private directory under the pls cache dir, or
+// the system temp dir (TMPDIR) without one, so concurrent installs never share paths. It is
+// removed when dropped.
+fn scratch_dir() -> io::Result<TempDir> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("pls-");
+    match cache_dir() {
+        Some(cache) => {
+            fs::create_dir_all(&cache)?;
+            builder.tempdir_in(cache)
+        }
+        None => builder.tempdir(),
+    }
+}
+
+// Archives name their top folder after the build, so look for the bin folder holding the tool
+fn find_bin_dir(dir: &Path, bin: &str) -> io::Result<Option<PathBuf>> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_name() == "bin" && path.join(bin).is_file() {
+            return Ok(Some(path));
+        }
+        if let Some(found) = find_bin_dir(&path, bin)? {
+            return Ok(Some(found));
+        }
+    }
+    Ok(None)
+}
+
+// Unpacks a verified download, an archive the extractor refuses counts as a failed install
+fn unpack(archive_path: &Path, dir: &Path) -> bool {
+    match archive::extract(archive_path, dir, 0, true) {
+        Ok(_) => true,
+        Err(e) => {
+            println!(
+                "{} {} {}",
+                "Error:".red().bold(),
+                format!("Failed to extract {}:", archive_path.to_string_lossy()).white(),
+                e.to_string().white()
+            );
+            false
+        }
+    }
+}
+
+// Copies ffmpeg, ffprobe and ffplay out of an unpacked archive into staging
+fn stage_ffmpeg(unpacked: &Path, staging: &Path) -> io::Result<bool> {
+    let Some(bin) = find_bin_dir(unpacked, &Tool::Ffmpeg.bin_name())? else {
+        println!(
+            "{} {}",
+            "Error:".red().bold(),
+            "The ffmpeg archive has no bin folder with ffmpeg in it.".white()
+        );
+        return Ok(false);
+    };
+    for name in Tool::Ffmpeg.files() {
+        let source = bin.join(&name);
+        if source.is_file() {
+            let dest = staging.join(&name);
+            fs::copy(&source, &dest)?;
+            #[cfg(unix)]
+            fs::set_permissions(&dest, Permissions::from_mode(0o755))?;
+        }
+    }
+    Ok(true)
+}
+
+fn remove_path(path: &Path) -> io::Result<()> {
+    if path.is_dir() {
+        fs::remove_dir_all(path)
+    } else if path.exists() {
+        fs::remove_file(path)
+    } else {
+        Ok(())
+    }
+}
+
+// Makes sure the staged tool runs, then swaps it in while the replaced files go to libs/previous
+fn commit_staging(
+    libs: &Path,
+    staging: &Path,
+    tool: Tool,
+    source: &str,
+    sha256: &str,
+    verified: bool,
+) -> io::Result<bool> {
+    let staged_bin = staging.join(tool.bin_name());
+    let Some(version) = tool.version(&staged_bin) else {
+        println!(
+            "{} {} {}",
+            "Error:".red().bold(),
+            format!("The downloaded {} doesn't run,", tool.name()).white(),
+            "keeping the installed version.".white()
+        );
+        return Ok(false);
+    };
+    write_receipt(staging, tool, source, sha256, verified, &version)?;
+
+    let previous = libs.join("previous");
+    fs::create_dir_all(&previous)?;
+    for name in tool.files() {
+        let staged = staging.join(&name);
+        if !staged.exists() {
+            continue;
+        }
+        let installed = libs.join(&name);
+        let kept = previous.join(&name);
+        remove_path(&kept)?;
+        if installed.exists() {
+            fs::rename(&installed, &kept)?;
+        }
+        fs::rename(&staged, &installed)?;
+    }
+    Ok(true)
+}
+
+// Swaps the installed files with the ones kept in libs/previous, so rolling back twice redoes the update
+pub fn rollback(tools: &[Tool]) -> io::Result<()> {
+    let Some(libs) = libs_dir() else {
+        println!(
+            "{} {} {}",
+            "Error:".red().bold(),
+            "Failed".white().bold(),
+            "to determine home directory.".white()
+        );
+        return Ok(());
+    };
+    let previous = libs.join("previous");
+    let _lock = lock_libs(&libs)?;
+
+    for tool in tools {
+        if !previous.join(tool.bin_name()).exists() {
+            println!(
+                "{} {} {}",
+                "Error:".red().bold(),
+                "No previous version of".white(),
+                tool.name().blue().bold()
+            );
+            continue;
+        }
+
+        let staging = staging_dir(&libs)?;
+        for name in tool.files() {
+            let installed = libs.join(&name);
+            let kept = previous.join(&name);
+            let swapped = staging.join(&name);
+            if installed.exists() {
+                fs::rename(&installed, &swapped)?;
+            }
+            if kept.exists() {
+                fs::rename(&kept, &installed)?;
+            }
+            if swapped.exists() {
+                fs::rename(&swapped, &kept)?;
+            }
+        }
+        fs::remove_dir_all(&staging)?;
+        println!(
+            "{} {} {}",
+            tool.name().blue().bold(),
+            "has been successfully".white(),
+            "rolled back.".white().bold()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn ytdlp_install(
+    update: bool,
+    ytdlp_url: String,
+    ytdlp_sums: Option<String>,
+    libs: PathBuf,
+    termux: PathBuf,
+) -> io::Result<()> {
+    announce("yt-dlp", update);
+
+    let staging = staging_dir(&libs)?;
+    let staged_bin = staging.join("yt-dlp");
+    let sha256;
+    if termux.exists() && termux.is_dir() {
+        let scratch = scratch_dir()?;
+        let zip = scratch.path().join("yt-dlp.tar.xz");
+        fetch_artifact(&ytdlp_url, &zip)?;
+        sha256 = verify_download(&ytdlp_url, &zip, ytdlp_sums.as_deref())?
+            .filter(|_| unpack(&zip, &staging));
+    } else {
+        let download = staging.join("yt-dlp.download");
+        fetch_artifact(&ytdlp_url, &download)?;
+        sha256 = verify_download(&ytdlp_url, &download, ytdlp_sums.as_deref())?;
+        if sha256.is_some() {
+            fs::rename(&download, &staged_bin)?;
+            fs::set_permissions(&staged_bin, Permissions::from_mode(0o755))?;
+        }
+    }
+
+    let success = match sha256 {
+        Some(sha256) => commit_staging(
+            &libs,
+            &staging,
+            Tool::Ytdlp,
+            &ytdlp_url,
+            &sha256,
+            ytdlp_sums.is_some(),
+        )?,
+        None => false,
+    };
+    fs::remove_dir_all(&staging)?;
+    install_result("yt-dlp", update, success);
+    Ok(())
+}
+
+#[cfg(unix)]
+fn ffmpeg_install(
+    update: bool,
+    libs: PathBuf,
+    ffmpeg_url: String,
+    ffmpeg_sums: Option<String>,
+) -> io::Result<()> {
+    announce("ffmpeg", update);
+
+    let scratch = scratch_dir()?;
+    let ffmpeg_zip = scratch.path().join("ffmpeg.tar.xz");
+    fetch_artifact(&ffmpeg_url, &ffmpeg_zip)?;
+    let Some(sha256) = verify_download(&ffmpeg_url, &ffmpeg_zip, ffmpeg_sums.as_deref())? else {
+        install_result("ffmpeg", update, false);
+        return Ok(());
+    };
+    let unpacked = scratch.path().join("unpacked");
+    if !unpack(&ffmpeg_zip, &unpacked) {
+        install_result("ffmpeg", update, false);
+        return Ok(());
+    }
+    fs::remove_file(ffmpeg_zip)?;
+
+    let staging = staging_dir(&libs)?;
+    let success = stage_ffmpeg(&unpacked, &staging)?
+        && commit_staging(
+            &libs,
+            &staging,
+            Tool::Ffmpeg,
+            &ffmpeg_url,
+            &sha256,
+            ffmpeg_sums.is_some(),
+        )?;
+    fs::remove_dir_all(&staging)?;
+    install_result("ffmpeg", update, success);
+    Ok(())
+}
+
+#[cfg(unix)]
+pub fn ytdlp_check(update: bool, tools: &[Tool]) -> io::Result<()> {
+    if let (Some(home), Some(libs)) = (home_dir(), libs_dir()) {
+        let ytdlp_bin = libs.join("yt-dlp");
+        let termux = home.join(".termux");
+        let platform = Platform::current(termux.exists() && termux.is_dir());
+        let Some(sources) = load_sources() else {
+            return Ok(());
+        };
+        let ffmpeg_bin = libs.join("ffmpeg");
+        let ffprobe_bin = libs.join("ffprobe");
+        let ffplay_bin = libs.join("ffplay");
+        // Whatever another pls installed while we waited counts, so check the files only now
+        let _lock = lock_libs(&libs)?;
+        let termux_incomplete =
+            (!libs.join("python3.12").exists() || !libs.join("yt_dlp").exists()) && termux.exists();
+        if tools.contains(&Tool::Ytdlp) && (!ytdlp_bin.exists() || update || termux_incomplete) {
+            if let Some((ytdlp_url, ytdlp_sums)) =
+                tool_source(&sources, Tool::Ytdlp, &platform, &libs)
+            {
+                // An installed yt-dlp that matches the pin stays on updates
+                if !ytdlp_bin.exists()
+                    || termux_incomplete
+                    || !pinned_and_current(&libs, &ytdlp_url)
+                {
+                    ytdlp_install(update, ytdlp_url, ytdlp_sums, libs.clone(), termux.clone())?;
+                }
+            }
+        }
+        if tools.contains(&Tool::Ffmpeg)
+            && (!ffmpeg_bin.exists() || !ffplay_bin.exists() || !ffprobe_bin.exists() || update)
+        {
+            if let Some((ffmpeg_url, ffmpeg_sums)) =
+                tool_source(&sources, Tool::Ffmpeg, &platform, &libs)
+            {
+                ffmpeg_install(update, libs, ffmpeg_url, ffmpeg_sums)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+fn ytdlp_install(
+    update: bool,
+    libs: PathBuf,
+    ytdlp_url: String,
+    ytdlp_sums: Option<String>,
+) -> io::Result<()> {
+    announce("yt-dlp", update);
+
+    let staging = staging_dir(&libs)?;
+    let download = staging.join("yt-dlp.download");
+    fetch_artifact(&ytdlp_url, &download)?;
+    let success = match verify_download(&ytdlp_url, &download, ytdlp_sums.as_deref())? {
+        Some(sha256) => {
+            fs::rename(&download, staging.join("yt-dlp.exe"))?;
+            commit_staging(
+                &libs,
+                &staging,
+                Tool::Ytdlp,
+                &ytdlp_url,
+                &sha256,
+                ytdlp_sums.is_some(),
+            )?
+        }
+        None => false,
+    };
+    fs::remove_dir_all(&staging)?;
+    install_result("yt-dlp", update, success);
+    Ok(())
+}
+
+#[cfg(windows)]
+fn ffmpeg_install(
+    update: bool,
+    libs: PathBuf,
+    ffmpeg_url: String,
+    ffmpeg_sums: Option<String>,
+) -> io::Result<()> {
+    announce("ffmpeg", update);
+    let scratch = scratch_dir()?;
+    let ffmpeg_zip = scratch.path().join("ffmpeg.zip");
+    fetch_artifact(&ffmpeg_url, &ffmpeg_zip)?;
+    let Some(sha256) = verify_download(&ffmpeg_url, &ffmpeg_zip, ffmpeg_sums.as_deref())? else {
+        install_result("ffmpeg", update, false);
+        return Ok(());
+    };
+    let unpacked = scratch.path().join("unpacked");
+    if !unpack(&ffmpeg_zip, &unpacked) {
+        install_result("ffmpeg", update, false);
+        return Ok(());
+    }
+    fs::remove_file(ffmpeg_zip)?;
+
+    let staging = staging_dir(&libs)?;
+    let success = stage_ffmpeg(&unpacked, &staging)?
+        && commit_staging(
+            &libs,
+            &staging,
+            Tool::Ffmpeg,
+            &ffmpeg_url,
+            &sha256,
+            ffmpeg_sums.is_some(),
+        )?;
+    fs::remove_dir_all(&staging)?;
+    install_result("ffmpeg", update, success);
+    Ok(())
+}
+
+#[cfg(windows)]
+pub fn ytdlp_check(update: bool, tools: &[Tool]) -> io::Result<()> {
+    if let Some(libs) = libs_dir() {
+        let ytdlp_bin = libs.join("yt-dlp.exe");
+        let Some(sources) = load_sources() else {
+            return Ok(());
+        };
+        let platform = Platform::current(false);
+        let ffmpeg_bin = libs.join("ffmpeg.exe");
+        let ffprobe_bin = libs.join("ffprobe.exe");
+        let ffplay_bin = libs.join("ffplay.exe");
+
+        // Whatever another pls installed while we waited counts, so check the files only now
+        let _lock = lock_libs(&libs)?;
+        if tools.contains(&Tool::Ytdlp) && (!ytdlp_bin.exists() || update) {
+            if let Some((ytdlp_url, ytdlp_sums)) =
+                tool_source(&sources, Tool::Ytdlp, &platform, &libs)
+            {
+                // An installed yt-dlp that matches the pin stays on updates
+                if !ytdlp_bin.exists() || !pinned_and_current(&libs, &ytdlp_url) {
+                    ytdlp_install(update, libs.clone(), ytdlp_url, ytdlp_sums)?;
+                }
+            }
+        }
+        if tools.contains(&Tool::Ffmpeg)
+            && (!ffmpeg_bin.exists() || !ffplay_bin.exists() || !ffprobe_bin.exists() || update)
+        {
+            if let Some((ffmpeg_url, ffmpeg_sums)) =
+                tool_source(&sources, Tool::Ffmpeg, &platform, &libs)
+            {
+                ffmpeg_install(update, libs, ffmpeg_url, ffmpeg_sums)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+const LATEST_YTDLP_RELEASE: &str = "https://api.github.com/repos/yt-dlp/yt-dlp/releases/latest";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+}
+
+// Days a bundled yt-dlp may age before pls looks for a newer release, 0 turns the check off
+fn update_check_days() -> u64 {
+    std::env::var("PLS_UPDATE_DAYS")
+        .ok()
+        .and_then(|days| days.trim().parse().ok())
+        .unwrap_or(14)
+}
+
+fn latest_ytdlp_tag() -> Result<String, Box<dyn Error>> {
+    let release: Release = Client::new()
+        .get(LATEST_YTDLP_RELEASE)
+        .header(USER_AGENT, "pls")
+        .timeout(Duration::from_secs(10))
+        .send()?
+        .error_for_status()?
+        .json()?;
+    Ok(release.tag_name)
+}
+
+// Only a bundled yt-dlp that follows the latest release can be updated by pls
+pub fn ytdlp_updatable(deps: &MediaDeps) -> bool {
+    let termux = home_dir().is_some_and(|home| home.join(".termux").is_dir());
+    libs_dir().is_some_and(|libs| {
+        deps.ytdlp == libs.join(Tool::Ytdlp.bin_name()) && read_pin(&libs).is_none() && !termux
+    })
+}
+
+// Broken extractors, not errors like "Private video" or "Video unavailable" that an update
+// can't fix. yt-dlp asks to report unexpected extractor errors as issues.
+pub fn is_extractor_error(line: &str) -> bool {
+    line.starts_with("ERROR:")
+        && (line.contains("Unable to extract")
+            || line.contains("Failed to extract")
+            || line.contains("extraction failed")
+            || line.contains("please report this issue"))
+}
+
+// Updating only helps when a newer release is out than the installed one
+pub fn newer_ytdlp_available(deps: &MediaDeps) -> bool {
+    let Some(libs) = libs_dir() else {
+        return false;
+    };
+    let installed = receipt_value(&read_receipt(&libs, Tool::Ytdlp), "version")
+        .map(|version| version.to_string())
+        .or_else(|| Tool::Ytdlp.version(&deps.ytdlp));
+    match (installed, latest_ytdlp_tag()) {
+        (Some(installed), Ok(latest)) => installed != latest,
+        _ => false,
+    }
+}
+
+// Installed and latest version, known only when the latest release differs
+pub type UpdateCheck = JoinHandle<Option<(String, String)>>;
+
+// Looks up the latest yt-dlp release while the download runs, once a day at most and only
+// when the bundled yt-dlp is older than PLS_UPDATE_DAYS
+pub fn start_update_check(deps: &MediaDeps) -> Option<UpdateCheck> {
+    let days = update_check_days();
+    if days == 0 || !ytdlp_updatable(deps) {
+        return None;
+    }
+    let libs = libs_dir()?;
+    let receipt = read_receipt(&libs, Tool::Ytdlp);
+    let now = now_secs();
+    let installed = receipt_value(&receipt, "installed")
+        .and_then(|installed| installed.parse::<u64>().ok())
+        .unwrap_or_default();
+    let checked = fs::read_to_string(libs.join("yt-dlp.checked"))
+        .ok()
+        .and_then(|checked| checked.trim().parse::<u64>().ok())
+        .unwrap_or_default();
+    if now.saturating_sub(installed) < days * 86400 || now.saturating_sub(checked) < 86400 {
+        return None;
+    }
+    fs::write(libs.join("yt-dlp.checked"), now.to_string()).ok()?;
+
+    let version = receipt_value(&receipt, "version")
+        .map(|version| version.to_string())
+        .or_else(|| Tool::Ytdlp.version(&deps.ytdlp))?;
+    Some(thread::spawn(move || {
+        let latest = latest_ytdlp_tag().ok()?;
+        (latest != version).then_some((version, latest))
+    }))
+}
+
+// Asks whether to update yt-dlp when the check found a newer release
+pub fn offer_update(check: UpdateCheck) -> io::Result<()> {
+    let Ok(Some((version, latest))) = check.join() else {
+        return Ok(());
+    };
+    println!(
+        "{} {} {}",
+        "\nyt-dlp".blue().bold(),
+        latest.white().bold(),
+        format!("is available, you have {}.", version).white()
+    );
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        println!(
+            "{} {}",
+            "Run".white(),
+            "pls deps update yt-dlp".white().bold()
+        );
+        return Ok(());
+    }
+    print!("{}", "Update yt-dlp now? [Y/n] ".white());
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    if matches!(answer.trim().to_lowercase().as_str(), "" | "y" | "yes") {
+        ytdlp_check(true, &[Tool::Ytdlp])?;
+    }
+    Ok(())
+}
+
+// Picks the tools a deps subcommand works on, no argument means both of them
+fn tools_from_args(args: &[String]) -> Option<Vec<Tool>> {
+    if args.is_empty() {
+        return Some(vec![Tool::Ytdlp, Tool::Ffmpeg]);
+    }
+    args.iter().map(|arg| Tool::parse(arg)).collect()
+}
+
+fn dir_size(path: &Path) -> u64 {
+    if path.is_dir() {
+        fs::read_dir(path)
+            .map(|entries| entries.flatten().map(|entry| dir_size(&entry.path())).sum())
+            .unwrap_or_default()
+    } else {
+        fs::metadata(path)
+            .map(|meta| meta.len())
+            .unwrap_or_default()
+    }
+}
+
+// Formats unix seconds as a UTC date, using the days to civil date conversion by Howard Hinnant
+fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let (hour, minute) = ((secs % 86400) / 3600, (secs % 3600) / 60);
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02} UTC",
+        year, month, day, hour, minute
+    )
+}
+
+fn status_line(label: &str, value: &str) {
+    println!(
+        "  {} {:<11}{}",
+        "⟶".blue(),
+        format!("{}:", label).white(),
+        value.white().bold()
+    );
+}
+
+fn status(libs: &Path, tools: &[Tool]) {
+    for tool in tools {
+        println!("{}", tool.name().blue().bold());
+        let bin = libs.join(tool.bin_name());
+        if bin.exists() {
+            let receipt = read_receipt(libs, *tool);
+            let size: u64 = tool
+                .files()
+                .iter()
+                .map(|name| dir_size(&libs.join(name)))
+                .sum();
+            status_line("Path", &bin.to_string_lossy());
+            status_line(
+                "Version",
+                &tool.version(&bin).unwrap_or_else(|| "unknown".to_string()),
+            );
+            status_line("Size", &HumanBytes(size).to_string());
+            status_line(
+                "Source",
+                receipt_value(&receipt, "source").unwrap_or("unknown"),
+            );
+            let installed = receipt_value(&receipt, "installed")
+                .and_then(|secs| secs.parse().ok())
+                .map(format_timestamp);
+            status_line("Installed", installed.as_deref().unwrap_or("unknown"));
+            if let Some(sha256) = receipt_value(&receipt, "sha256") {
+                let verified = if receipt_value(&receipt, "verified") == Some("true") {
+                    "verified"
+                } else {
+                    "unverified"
+                };
+                status_line("SHA-256", &format!("{} ({})", sha256, verified));
+            }
+            if *tool == Tool::Ytdlp {
+                if let Some(pin) = read_pin(libs) {
+                    status_line("Pinned", &pin);
+                }
+            }
+            if libs.join("previous").join(tool.bin_name()).exists() {
+                status_line("Rollback", "previous version kept");
+            }
+        } else {
+            status_line("Bundled", "not installed");
+        }
+        if let Some(system) = find_in_path(tool.name()) {
+            let version = tool
+                .version(&system)
+                .unwrap_or_else(|| "unknown".to_string());
+            status_line(
+                "System",
+                &format!("{} ({})", system.to_string_lossy(), version),
+            );
+        }
+    }
+}
+
+fn pin(libs: &Path, version: &str) -> io::Result<()> {
+    #[cfg(unix)]
+    if home_dir().is_some_and(|home| home.join(".termux").is_dir()) {
+        println!(
+            "{} {}",
+            "Error:".red().bold(),
+            "Pinning yt-dlp isn't available on Termux.".white()
+        );
+        return Ok(());
+    }
+    if version.is_empty() || version.contains(['/', '\\']) {
+        println!(
+            "{} {} {}",
+            "Error:".red().bold(),
+            "Invalid yt-dlp release tag:".white(),
+            version.white().bold()
+        );
+        return Ok(());
+    }
+
+    // A mirror holds one yt-dlp build, the pin would only claim a release that isn't served
+    if version != "latest" {
+        let Some(sources) = load_sources() else {
+            return Ok(());
+        };
+        let platform = Platform::current(false);
+        if sources
+            .find(Tool::Ytdlp.name(), &platform)
+            .is_some_and(|artifact| !artifact.pinnable())
+        {
+            println!(
+                "{} {}",
+                "Error:".red().bold(),
+                "The yt-dlp source has no {release} to pin, unset PLS_MIRROR and the manifest base or list a {release} URL in the deps manifest."
+                    .white()
+            );
+            return Ok(());
+        }
+    }
+
+    fs::create_dir_all(libs)?;
+    if version == "latest" {
+        remove_path(&libs.join("yt-dlp.pin"))?;
+    } else {
+        fs::write(libs.join("yt-dlp.pin"), version)?;
+    }
+    ytdlp_check(true, &[Tool::Ytdlp])
+}
+
+fn remove(data: &Path) -> io::Result<()> {
+    if let Some(cache) = cache_dir().filter(|cache| cache.exists()) {
+        fs::remove_dir_all(cache)?;
+    }
+    if !data.exists() {
+        println!(
+            "{} {}",
+            "Nothing to remove,".white(),
+            data.to_string_lossy().white().bold()
+        );
+        return Ok(());
+    }
+    fs::remove_dir_all(data)?;
+    println!(
+        "{} {} {}",
+        data.to_string_lossy().white().bold(),
+        "has been successfully".white(),
+        "removed.".white().bold()
+    );
+    Ok(())
+}
+
+pub fn deps_command(args: &[String]) -> io::Result<()> {
+    let Some((data, libs)) = data_dir().zip(libs_dir()) else {
+        println!(
+            "{} {} {}",
+            "Error:".red().bold(),
+            "Failed".white().bold(),
+            "to determine home directory.".white()
+        );
+        return Ok(());
+    };
+    let Some((command, rest)) = args.split_first() else {
+        println!(
+            "{} {} {}",
+            "Error:".red().bold(),
+            "Missing deps command, available:".white(),
+            "status, install, update, pin, remove, rollback"
+                .white()
+                .bold()
+        );
+        return Ok(());
+    };
+
+    if command == "pin" {
+        match rest {
+            [version] => pin(&libs, version)?,
+            _ => println!(
+                "{} {} {}",
+                "Error:".red().bold(),
+                "Usage:".white(),
+                "pls deps pin <version|latest>".white().bold()
+            ),
+        }
+        return Ok(());
+    }
+    if command == "remove" {
+        return remove(&data);
+    }
+
+    let Some(tools) = tools_from_args(rest) else {
+        println!(
+            "{} {} {}",
+            "Error:".red().bold(),
+            "Unknown tool, expected".white(),
+            "yt-dlp or ffmpeg".white().bold()
+        );
+        return Ok(());
+    };
+    if command == "status" {
+        status(&libs, &tools);
+    } else if command == "install" {
+        ytdlp_check(false, &tools)?;
+    } else if command == "update" {
+        ytdlp_check(true, &tools)?;
+    } else if command == "rollback" {
+        rollback(&tools)?;
+    } else {
+        println!(
+            "{} {} {}",
+            "Error:".red().bold(),
+            "Unknown deps command:".white(),
+            command.white().bold()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve;
+    use tempfile::tempdir;
+
+    const FAKE_YTDLP: &[u8] = b"#!/bin/sh\necho 2024.12.13\n";
+
+    fn sha256(data: &[u8]) -> String {
+        Sha256::digest(data)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    #[test]
+    fn only_broken_extractors_count() {
+        assert!(is_extractor_error(
+            "ERROR: [youtube] abc: Unable to extract uploader id; please report this issue on https://github.com/yt-dlp/yt-dlp/issues"
+        ));
+        assert!(is_extractor_error(
+            "ERROR: [youtube] abc: Failed to extract any player response"
+        ));
+        assert!(!is_extractor_error(
+            "ERROR: [youtube] abc: Video unavailable"
+        ));
+        assert!(!is_extractor_error("ERROR: [youtube] abc: Private video"));
+        assert!(!is_extractor_error(
+            "ERROR: [Errno 28] No space left on device"
+        ));
+    }
+
+    #[test]
+    fn checksums_are_found_by_file_name() {
+        let manifest = "aaaa  yt-dlp\nBBBB *yt-dlp_linux\n";
+        assert_eq!(
+            parse_checksums(manifest, "yt-dlp_linux").as_deref(),
+            Some("bbbb")
+        );
+        assert_eq!(parse_checksums(manifest, "yt-dlp").as_deref(), Some("aaaa"));
+        assert_eq!(parse_checksums(manifest, "yt-dlp_macos"), None);
+        // .sha256 files for a single artifact may hold nothing but the digest
+        assert_eq!(parse_checksums("CCCC\n", "pls").as_deref(), Some("cccc"));
+    }
+
+    #[test]
+    fn verify_download_checks_the_published_digest() {
+        let base = serve(vec![
+            (
+                "/SHA2-256SUMS",
+                200,
+                format!("{}  yt-dlp_linux\n", sha256(b"good")).into_bytes(),
+            ),
+            ("/yt-dlp_linux.sha256", 200, sha256(b"good").into_bytes()),
+        ]);
+        let dir = tempdir().unwrap();
+        let url = format!("{}/yt-dlp_linux", base);
+        let path = dir.path().join("download");
+        let sums = format!("{}/SHA2-256SUMS", base);
+        let single = format!("{}/yt-dlp_linux.sha256", base);
+
+        fs::write(&path, b"good").unwrap();
+        let verified = verify_download(&url, &path, Some(&sums)).unwrap();
+        assert_eq!(verified, Some(sha256(b"good")));
+        assert!(path.exists());
+
+        let verified = verify_download(&url, &path, Some(&single)).unwrap();
+        assert_eq!(verified, Some(sha256(b"good")));
+
+        fs::write(&path, b"tampered").unwrap();
+        assert_eq!(verify_download(&url, &path, Some(&sums)).unwrap(), None);
+        assert!(!path.exists());
+
+        // A manifest that doesn't list the file counts as a failure too
+        fs::write(&path, b"good").unwrap();
+        let other = format!("{}/yt-dlp_macos", base);
+        assert_eq!(verify_download(&other, &path, Some(&sums)).unwrap(), None);
+        assert!(!path.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn install_writes_a_verified_receipt() {
+        let base = serve(vec![
+            ("/yt-dlp_linux", 200, FAKE_YTDLP.to_vec()),
+            (
+                "/SHA2-256SUMS",
+                200,
+                format!("{}  yt-dlp_linux\n", sha256(FAKE_YTDLP)).into_bytes(),
+            ),
+        ]);
+        let dir = tempdir().unwrap();
+        let libs = dir.path().join("libs");
+        fs::create_dir_all(&libs).unwrap();
+        ytdlp_install(
+            false,
+            format!("{}/yt-dlp_linux", base),
+            Some(format!("{}/SHA2-256SUMS", base)),
+            libs.clone(),
+            dir.path().join("no-termux"),
+        )
+        .unwrap();
+
+        assert_eq!(fs::read(libs.join("yt-dlp")).unwrap(), FAKE_YTDLP);
+        let receipt = read_receipt(&libs, Tool::Ytdlp);
+        assert_eq!(receipt_value(&receipt, "verified"), Some("true"));
+        assert_eq!(
+            receipt_value(&receipt, "sha256"),
+            Some(sha256(FAKE_YTDLP).as_str())
+        );
+        assert_eq!(receipt_value(&receipt, "version"), Some("2024.12.13"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn install_refuses_a_mismatch() {
+        let base = serve(vec![
+            ("/yt-dlp_linux", 200, FAKE_YTDLP.to_vec()),
+            (
+                "/SHA2-256SUMS",
+                200,
+                format!("{}  yt-dlp_linux\n", sha256(b"something else")).into_bytes(),
+            ),
+        ]);
+        let dir = tempdir().unwrap();
+        let libs = dir.path().join("libs");
+        fs::create_dir_all(&libs).unwrap();
+        ytdlp_install(
+            false,
+            format!("{}/yt-dlp_linux", base),
+            Some(format!("{}/SHA2-256SUMS", base)),
+            libs.clone(),
+            dir.path().join("no-termux"),
+        )
+        .unwrap();
+
+        assert!(!libs.join("yt-dlp").exists());
+        assert!(!libs.join("yt-dlp.receipt").exists());
+        assert!(!libs.join(".staging").exists());
+    }
+}
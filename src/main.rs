@@ -1,281 +1,309 @@
+mod archive;
+mod config;
+mod deps;
+mod manifest;
+mod output;
+mod paths;
+mod progress;
+mod self_update;
+#[cfg(test)]
+mod test_server;
+mod theme;
+
+use archive::Compression;
 use clap::Parser;
 use colored::*;
+use config::Config;
+use deps::{DepsMode, Tool};
 use dirs::home_dir;
-use indicatif::{ProgressBar, ProgressStyle};
+use progress::Progress;
 use reqwest::blocking::Client;
+use reqwest::header::{HeaderName, HeaderValue, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::redirect::Policy;
+use reqwest::{Proxy, StatusCode};
+use std::fs;
 use std::fs::File;
-use std::fs::{self, Permissions};
 use std::io::copy;
-use std::io::BufReader;
 use std::io::{self};
-#[cfg(unix)]
-use std::os::unix::fs::PermissionsExt;
+use std::io::{BufRead, BufReader, Write};
 use std::path::Path;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
-#[cfg(unix)]
-use tar::Archive;
-use terminal_size::{terminal_size, Width};
+use std::thread;
+use std::time::Duration;
 use url::Url;
-#[cfg(unix)]
-use xz2::read::XzDecoder;
-#[cfg(windows)]
-use zip::read::ZipArchive;
 
 #[derive(Parser)]
 #[command(disable_help_flag = true)]
 #[command(allow_hyphen_values = true)]
 struct Cli {
-    #[arg(default_value_t = String::new())]
-    command: String,
+    // Everything is collected as is, so a `--` after the URL reaches main untouched
     #[arg(trailing_var_arg = true)]
     args: Vec<String>,
 }
 
 #[cfg(windows)]
 fn detect_os() -> String {
-    let system = "win".to_string();
-    system
+    "win".to_string()
 }
 
 #[cfg(unix)]
 fn detect_os() -> String {
-    let system = "unix".to_string();
-    system
+    "unix".to_string()
 }
 
-fn download_file(url: &str, output_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+// Returns the path the file was saved to, --decompress may have dropped its extension
+fn download_file(
+    url: &str,
+    output_path: &str,
+    file_options: &FileOptions,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let network = &file_options.network;
     // Create an HTTP client and send the GET request
-    let client = Client::new();
-    let mut response = client.get(url).send()?;
-    let term_width = match terminal_size() {
-        Some((Width(w), _)) => w,
-        None => 80, // fallback default
+    let mut client = Client::builder().redirect(Policy::custom(|attempt| {
+        // reqwest's default policy gives up after 10 redirects as well
+        if attempt.previous().len() > 10 {
+            attempt.error("too many redirects")
+        } else {
+            output::verbose(1, &format!("* Redirected to {}", attempt.url()));
+            attempt.follow()
+        }
+    }));
+    if let Some(proxy) = &network.proxy {
+        client = client.proxy(Proxy::all(proxy)?);
+    }
+    let client = client.build()?;
+    let retries = network.retries.unwrap_or(0);
+    let mut attempt = 0;
+    let response = loop {
+        let mut request = client.get(url);
+        for (name, value) in &network.headers {
+            request = request.header(name, value);
+        }
+        let request = request.build()?;
+        output::verbose(1, &format!("> {} {}", request.method(), request.url()));
+        for (name, value) in request.headers() {
+            output::verbose(
+                2,
+                &format!("> {}: {}", name, value.to_str().unwrap_or("<binary>")),
+            );
+        }
+        // Failed connections and overloaded servers are worth another try, anything else isn't
+        let result = client.execute(request);
+        let failure = match &result {
+            Ok(response)
+                if response.status().is_server_error()
+                    || response.status() == StatusCode::TOO_MANY_REQUESTS =>
+            {
+                Some(format!("Server answered {}", response.status()))
+            }
+            Ok(_) => None,
+            Err(e) if e.is_connect() || e.is_timeout() || e.is_request() => Some(e.to_string()),
+            Err(_) => None,
+        };
+        match failure {
+            Some(failure) if attempt < retries => {
+                attempt += 1;
+                let wait = Duration::from_secs(1 << (attempt - 1).min(5));
+                output::verbose(
+                    0,
+                    &format!(
+                        "* {}, retrying in {}s ({}/{})",
+                        failure,
+                        wait.as_secs(),
+                        attempt,
+                        retries
+                    ),
+                );
+                thread::sleep(wait);
+            }
+            // Out of retries, a 429 or 5xx answer fails like any other error status
+            _ => break result?.error_for_status()?,
+        }
+    };
+    let status = response.status().as_u16();
+    output::verbose(
+        1,
+        &format!("< {:?} {}", response.version(), response.status()),
+    );
+    let headers = response.headers().clone();
+    for (name, value) in &headers {
+        output::verbose(
+            2,
+            &format!("< {}: {}", name, value.to_str().unwrap_or("<binary>")),
+        );
+    }
+    // Error pages are never saved as the download
+    let response = response.error_for_status()?;
+    let mut body = BufReader::new(response);
+
+    // Headers decide what to decompress, the first bytes of the body when they don't say
+    let compression = if file_options.decompress || file_options.untar.is_some() {
+        headers
+            .get(CONTENT_ENCODING)
+            .and_then(|value| value.to_str().ok())
+            .and_then(Compression::from_encoding)
+            .or_else(|| {
+                headers
+                    .get(CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(Compression::from_content_type)
+            })
+            .or_else(|| Compression::from_magic(body.fill_buf().ok()?))
+    } else {
+        None
     };
-    let url_length = url.chars().count();
+    if file_options.untar.is_some()
+        && compression.is_none()
+        && body.fill_buf()?.starts_with(b"PK\x03\x04")
+    {
+        return Err("--untar only unpacks tar archives, use -x for zip files".into());
+    }
+    let mut output_path = output_path.to_string();
+    if let Some(compression) =
+        compression.filter(|_| file_options.untar.is_none() && !file_options.stdout)
+    {
+        let path = PathBuf::from(&output_path);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        if let Some(plain) = archive::strip_extension(&name, compression) {
+            let plain_path = path.with_file_name(plain).to_string_lossy().to_string();
+            if Path::new(&plain_path).exists() && !file_options.force {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} already exists!", plain_path),
+                )
+                .into());
+            }
+            output_path = plain_path;
+        }
+    }
+    let output_path = output_path.as_str();
 
     // Get the total size of the file (if available)
-    let total_size = response.content_length().unwrap_or(0);
+    let size = headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse().ok());
 
     let file_name = PathBuf::from(output_path)
         .file_name()
-        .unwrap()
-        .to_string_lossy()
-        .to_string();
-
-    let mut pink = Vec::new();
-    pink.push(255);
-    pink.push(180);
-    pink.push(215);
-    let mut blue = Vec::new();
-    blue.push(150);
-    blue.push(200);
-    blue.push(255);
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let destination = if file_options.stdout {
+        "-"
+    } else {
+        file_options.untar.as_deref().unwrap_or(output_path)
+    };
+    output::start(url, &file_name, destination, size, status);
+    output::verbose(1, &format!("* Saving to {}", destination));
 
     // Create and configure the progress bar
-    let pb = ProgressBar::new(total_size);
-    let pb_style_line = format!("{}", "|".truecolor(blue[0], blue[1], blue[2]));
-    let pb_style_first = "{msg}\n";
-    let percentage = "{percent}";
-    let percent = format!("{}", "%".truecolor(blue[0], blue[1], blue[2]));
-    let pb_style_end;
-    let pb_style_start;
-    let dw_file_name;
-    let finish_mes;
-    let pb_style_mid;
-    if term_width <= 100 {
-        let mut decorative_line = "".to_string();
-        for _i in 1..=term_width {
-            decorative_line = format!("{}{}", decorative_line, "─");
+    let theme = output::theme();
+    let progress = Progress::new(theme, url, &file_name, size, output::quiet());
+    let pb = progress.bar().clone();
+
+    // The progress bar follows the bytes coming off the network, which stay compressed
+    let reader = pb.wrap_read(body);
+    let reader = match compression {
+        Some(compression) => compression.decoder(BufReader::new(reader))?,
+        None => Box::new(reader),
+    };
+    let mut reader = output::Tracker::new(reader, pb.clone(), size);
+
+    // With -O - the body goes to stdout for pipes, the progress bar is drawn on stderr anyway
+    if file_options.stdout {
+        let mut stdout = io::stdout().lock();
+        match copy(&mut reader, &mut stdout).and_then(|_| stdout.flush()) {
+            // The reading end closing early, e.g. | head, is not an error
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => return Err(e.into()),
+            _ => {}
         }
+        progress.finish();
+        reader.finish(destination);
+        return Ok("-".to_string());
+    }
 
-        pb_style_start = format!(
-            "{} {}",
-            "⟶".truecolor(blue[0], blue[1], blue[2]),
-            "Progress:".truecolor(pink[0], pink[1], pink[2])
-        );
-        pb_style_end = format!(
-            "{} {} {}  {}  {} {}\n{} {}   {}\n{}",
-            "{bytes}".truecolor(pink[0], pink[1], pink[2]),
-            "/".truecolor(blue[0], blue[1], blue[2]),
-            "{total_bytes}".truecolor(pink[0], pink[1], pink[2]),
-            pb_style_line.truecolor(blue[0], blue[1], blue[2]),
-            "ETA:".truecolor(pink[0], pink[1], pink[2]),
-            "{eta}".truecolor(blue[0], blue[1], blue[2]),
-            "⟶".truecolor(blue[0], blue[1], blue[2]),
-            "Elapsed:".truecolor(pink[0], pink[1], pink[2]),
-            "{elapsed_precise}".truecolor(blue[0], blue[1], blue[2]),
-            decorative_line.truecolor(pink[0], pink[1], pink[2])
-        );
-        dw_file_name = format!(
-            "{}\n{} {}         {}",
-            decorative_line.truecolor(pink[0], pink[1], pink[2]),
-            "⟶".truecolor(blue[0], blue[1], blue[2]),
-            "File:".truecolor(pink[0], pink[1], pink[2]),
-            file_name.truecolor(blue[0], blue[1], blue[2])
-        );
-        finish_mes = format!(
-            "{}\n{} {}",
-            decorative_line.truecolor(pink[0], pink[1], pink[2]),
-            "●".truecolor(blue[0], blue[1], blue[2]),
-            "Download complete!".truecolor(pink[0], pink[1], pink[2])
-        );
-        pb_style_mid = "{bar:10.blue}";
-    } else {
-        let mut decorative_start_line;
-        let mut decorative_end_line;
-        let decorative_line = "│".truecolor(pink[0], pink[1], pink[2]);
-
-        let comparison =
-            "─────────────────────────────────────────────────────────────────────────────────┐";
-
-        if (url_length) >= comparison.chars().count() {
-            decorative_start_line = "┌───────────────".to_string();
-            decorative_end_line = "└───────────────".to_string();
-            for i in 1..=url_length + 1 {
-                if i == (url_length + 1) {
-                    decorative_start_line = format!(
-                        "{}{}",
-                        decorative_start_line.truecolor(pink[0], pink[1], pink[2]),
-                        "┐".truecolor(pink[0], pink[1], pink[2])
-                    );
-                    decorative_end_line = format!(
-                        "{}{}",
-                        decorative_end_line.truecolor(pink[0], pink[1], pink[2]),
-                        "┘".truecolor(pink[0], pink[1], pink[2])
-                    );
-                } else {
-                    decorative_start_line = format!(
-                        "{}{}",
-                        decorative_start_line.truecolor(pink[0], pink[1], pink[2]),
-                        "─".truecolor(pink[0], pink[1], pink[2])
-                    );
-                    decorative_end_line = format!(
-                        "{}{}",
-                        decorative_end_line.truecolor(pink[0], pink[1], pink[2]),
-                        "─".truecolor(pink[0], pink[1], pink[2])
-                    );
-                }
-            }
-        } else {
-            decorative_end_line = format!("{}","└──────────────────────────────────────────────────────────────────────────────────────────────┘".truecolor(pink[0], pink[1], pink[2]));
-            decorative_start_line = format!("{}","┌──────────────────────────────────────────────────────────────────────────────────────────────┐".truecolor(pink[0], pink[1], pink[2]));
+    // --untar unpacks straight from the network, the archive itself is never written
+    if let Some(dir) = &file_options.untar {
+        archive::unpack_tar(&mut reader, Path::new(dir), file_options.strip_components)?;
+        // Tar padding after the last entry is read too, so the bar ends at the full size
+        copy(&mut reader, &mut io::sink())?;
+        progress.finish();
+        reader.finish(destination);
+        if !output::quiet() {
+            theme.done("Unpacked to:", dir);
         }
-
-        pb_style_start = format!(
-            "{}{} {}",
-            decorative_line,
-            "⟶".truecolor(blue[0], blue[1], blue[2]),
-            "Progress:".truecolor(pink[0], pink[1], pink[2])
-        );
-        pb_style_end = format!(
-            "{} {} {}  {}  {} {}\n{}{} {}   {}\n{}",
-            "{bytes}".truecolor(pink[0], pink[1], pink[2]),
-            "/".truecolor(blue[0], blue[1], blue[2]),
-            "{total_bytes}".truecolor(pink[0], pink[1], pink[2]),
-            pb_style_line.truecolor(blue[0], blue[1], blue[2]),
-            "ETA:".truecolor(pink[0], pink[1], pink[2]),
-            "{eta}".truecolor(blue[0], blue[1], blue[2]),
-            decorative_line,
-            "⟶".truecolor(blue[0], blue[1], blue[2]),
-            "Elapsed:".truecolor(pink[0], pink[1], pink[2]),
-            "{elapsed_precise}".truecolor(blue[0], blue[1], blue[2]),
-            decorative_end_line
-        );
-        dw_file_name = format!(
-            "{}\n{}{} {} {}\n{}{} {}         {}",
-            decorative_start_line,
-            decorative_line,
-            "⟶".truecolor(blue[0], blue[1], blue[2]),
-            "Downloading:".truecolor(pink[0], pink[1], pink[2]),
-            url.truecolor(blue[0], blue[1], blue[2]),
-            decorative_line,
-            "⟶".truecolor(blue[0], blue[1], blue[2]),
-            "File:".truecolor(pink[0], pink[1], pink[2]),
-            file_name.truecolor(blue[0], blue[1], blue[2])
-        );
-        finish_mes = format!(
-            "{}\n{}{} {}",
-            decorative_start_line,
-            decorative_line,
-            "●".truecolor(blue[0], blue[1], blue[2]),
-            "Download complete!".truecolor(pink[0], pink[1], pink[2])
-        );
-        pb_style_mid = "{bar:40.blue}";
+        return Ok(dir.clone());
     }
-    let pb_style = format!(
-        "{}{}  {}  {}{}  {}  {}",
-        pb_style_first,
-        pb_style_start,
-        pb_style_mid,
-        percentage.truecolor(pink[0], pink[1], pink[2]),
-        percent,
-        pb_style_line,
-        pb_style_end
-    );
-    pb.set_style(
-        ProgressStyle::default_bar()
-            .template(&pb_style)
-            .unwrap()
-            .progress_chars("█▉▊▋▌▍▎▏  "),
-    );
-
-    pb.set_message(dw_file_name);
 
     // Open the output file
     let mut file = File::create(output_path)?;
 
-    // Wrap the file writer to update the progress bar
-    let mut writer = pb.wrap_write(&mut file);
-
     // Copy the response to the file in chunks
-    copy(&mut response, &mut writer)?;
+    copy(&mut reader, &mut file)?;
 
-    pb.finish_with_message(finish_mes);
-    println!(
-        "{} {} {}",
-        "\n ●".truecolor(blue[0], blue[1], blue[2]),
-        "File saved to:".truecolor(pink[0], pink[1], pink[2]),
-        output_path.truecolor(blue[0], blue[1], blue[2])
-    );
+    progress.finish();
+    reader.finish(destination);
+    if !output::quiet() {
+        theme.done("File saved to:", output_path);
+    }
 
-    Ok(())
+    Ok(output_path.to_string())
 }
 
 fn go(url: String, out: String) -> io::Result<()> {
-    match download_file(&url, &out) {
-        Ok(_) => {}
+    download_go(&url, &out, &FileOptions::default())?;
+    Ok(())
+}
+
+// Prints why a download failed, the saved path comes back when it succeeded
+fn download_go(url: &str, out: &str, file_options: &FileOptions) -> io::Result<Option<String>> {
+    match download_file(url, out, file_options) {
+        Ok(saved) => Ok(Some(saved)),
         Err(e) => {
-            println!("{} {}", "Error".red().bold(), e.to_string().white());
-            return Ok(());
+            output::error(error_code(e.as_ref()), &e.to_string());
+            Ok(None)
         }
     }
-    Ok(())
+}
+
+// Sorts download failures into the codes of JSON error events
+fn error_code(e: &(dyn std::error::Error + 'static)) -> &'static str {
+    if let Some(e) = e.downcast_ref::<reqwest::Error>() {
+        if e.is_status() {
+            "http"
+        } else {
+            "network"
+        }
+    } else if let Some(e) = e.downcast_ref::<io::Error>() {
+        match e.kind() {
+            io::ErrorKind::NotFound => "not_found",
+            io::ErrorKind::AlreadyExists => "exists",
+            _ => "io",
+        }
+    } else {
+        "download"
+    }
 }
 
 fn double_force() -> bool {
     let illegal = true;
-    println!(
-        "{} {}",
-        "Error:".red().bold(),
-        "-f/--force used twice!".white()
-    );
+    output::error("usage", "-f/--force used twice!");
     illegal
 }
 
 fn double_media() -> bool {
     let illegal = true;
-    println!(
-        "{} {}",
-        "Error:".red().bold(),
-        "-m/--media used twice!".white()
-    );
+    output::error("usage", "-m/--media used twice!");
     illegal
 }
 
+// URLs ending in / name a directory, not a file
 fn get_file_name_from_url(url_str: &str) -> Option<String> {
     let url = Url::parse(url_str).ok()?;
-    url.path_segments()?.last().map(|s| s.to_string())
+    url.path_segments()?
+        .next_back()
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
 }
 
 fn remove_slash(out: &str) -> String {
@@ -305,25 +333,81 @@ fn get_dir_from_path(path: &str) -> String {
         .unwrap_or_else(|| ".".to_string()) // Default to current directory
 }
 
-fn file_check_go(url: String, out: String, force: bool) -> io::Result<()> {
+fn file_check_go(
+    url: String,
+    out: String,
+    force: bool,
+    file_options: &FileOptions,
+) -> io::Result<()> {
     if Path::new(&out).is_file() && Path::new(&out).exists() {
-        if force == true {
+        if force {
             let mes = format!(
                 "{} {}",
                 "Error:".red().bold(),
                 "Failed to remove file!".white()
             );
             fs::remove_file(out.clone()).expect(&mes);
-            go(url, out)?;
+            file_go(url, out, file_options)?;
         } else {
-            println!(
-                "{} {}",
-                "Error:".red().bold(),
-                "File already exists!".white()
-            );
+            output::error("exists", "File already exists!");
         }
     } else {
-        go(url, out)?;
+        file_go(url, out, file_options)?;
+    }
+    Ok(())
+}
+
+// Downloads a file and does whatever the file options ask for afterwards
+fn file_go(url: String, out: String, file_options: &FileOptions) -> io::Result<()> {
+    let Some(saved) = download_go(&url, &out, file_options)? else {
+        return Ok(());
+    };
+    if file_options.extract {
+        extract_go(Path::new(&saved), file_options)?;
+    }
+    Ok(())
+}
+
+fn extract_go(archive_path: &Path, file_options: &FileOptions) -> io::Result<()> {
+    // Without a directory the archive is unpacked next to itself, like tar does in place
+    let dir = match &file_options.extract_dir {
+        Some(dir) => PathBuf::from(dir),
+        None => archive_path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from(".")),
+    };
+    match archive::extract(
+        archive_path,
+        &dir,
+        file_options.strip_components,
+        file_options.force,
+    ) {
+        Ok(_) => {
+            if output::json() {
+                output::extracted(&archive_path.to_string_lossy(), &dir.to_string_lossy());
+            } else if !output::quiet() {
+                output::theme().done("Extracted to:", &dir.to_string_lossy());
+            }
+            if file_options.delete_archive {
+                fs::remove_file(archive_path)?;
+            }
+        }
+        Err(e) => {
+            let code = match e.kind() {
+                io::ErrorKind::AlreadyExists => "exists",
+                _ => "extract",
+            };
+            output::error(
+                code,
+                &format!(
+                    "Failed to extract {}: {}",
+                    archive_path.to_string_lossy(),
+                    e
+                ),
+            );
+        }
     }
     Ok(())
 }
@@ -362,6 +446,11 @@ fn help() {
         "{}",
         "       This option allows overwriting files. URL and OUTPUT must be present, while choosing this option.".white()
     );
+    println!("{}", "\n       --no-force".white().bold());
+    println!(
+        "{}",
+        "       Never overwrites files, even when config.toml sets force = true.".white()
+    );
     println!(
         "{} {} {}",
         "\n       -m".white().bold(),
@@ -372,6 +461,194 @@ fn help() {
         "{}",
         "       This option lets you download videos from YouTube or any other site. URL and OUTPUT must be present, while choosing this option. Can be combined with -f/--force".white()
     );
+    println!(
+        "{} {} {} {}",
+        "\n       -O".white().bold(),
+        "OUTPUT".white(),
+        "or".white(),
+        "--stdout".white().bold()
+    );
+    println!(
+        "{}",
+        "       -O sets the output path like the second argument. -O - or --stdout writes the download to stdout instead, with the progress bar on stderr, so it can be piped: pls URL - | tar x".white()
+    );
+    println!("{} {}", "\n       --profile".white().bold(), "NAME".white());
+    println!(
+        "{}",
+        "       Uses the [profile.NAME] table of config.toml on top of its defaults, see FILES."
+            .white()
+    );
+    println!("{} {}", "\n       --retries".white().bold(), "N".white());
+    println!(
+        "{}",
+        "       Tries a download up to N more times when the connection fails or the server answers 429 or 5xx, waiting 1, 2, 4... seconds in between. Passed to yt-dlp with -m/--media.".white()
+    );
+    println!(
+        "{} {} {} {}",
+        "\n       -H".white().bold(),
+        "or".white(),
+        "--header".white().bold(),
+        "\"NAME: VALUE\"".white()
+    );
+    println!(
+        "{}",
+        "       Sends an extra HTTP header, can be repeated. Replaces a header of the same name from config.toml. Passed to yt-dlp with -m/--media.".white()
+    );
+    println!("{} {}", "\n       --proxy".white().bold(), "URL".white());
+    println!(
+        "{}",
+        "       Sends requests through an HTTP, HTTPS or SOCKS5 proxy, e.g. socks5://127.0.0.1:1080. Passed to yt-dlp with -m/--media.".white()
+    );
+    println!("{} {}", "\n       --theme".white().bold(), "NAME".white());
+    println!(
+        "{}",
+        "       Picks the look of the progress display: default, ascii (no Unicode box characters), mono (no colours) or minimal (no boxes). Without it the [theme] table of config.toml in the config folder is used, which can also set layout (auto, boxed, compact, minimal), compact_width, colors, label and accent RGB colours, arrow, bullet, separator, horizontal, vertical, corners, bar, bar_color, spinner and ellipsis. The display is laid out again when the terminal is resized, long URLs and file names are shortened in the middle.".white()
+    );
+    println!("{}", "\n       --json".white().bold());
+    println!(
+        "{}",
+        "       Prints newline-delimited JSON events instead of the progress bar and messages: start (url, status, file, path, size), progress every half second (bytes received, total, speed in bytes/s), finish (path, bytes and sha256 of the written data, duration in seconds), extract (archive, path) and error (code, message). Error codes are usage, config, exists, not_found, network, http, io, extract and download. Not available with -m/--media or --stdout.".white()
+    );
+    println!("{}", "\n       --decompress".white().bold());
+    println!(
+        "{}",
+        "       Decompresses gzip, xz, bzip2 and zstd downloads while they arrive, so only the plain file is written. The format comes from Content-Encoding, Content-Type or the first bytes, and the compression extension is dropped from the file name.".white()
+    );
+    println!("{} {}", "\n       --untar".white().bold(), "DIR".white());
+    println!(
+        "{}",
+        "       Unpacks a tar archive (plain, gzip, xz, bzip2 or zstd) into DIR while it downloads, the archive is never written to disk. Takes no OUTPUT, works with --strip-components.".white()
+    );
+    println!(
+        "{} {} {} {}",
+        "\n       -x".white().bold(),
+        "or".white(),
+        "--extract".white().bold(),
+        "[DIR]".white()
+    );
+    println!(
+        "{}",
+        "       Extracts the downloaded archive into DIR, or next to the archive without one. zip, tar, .tar.gz/.tgz, .tar.xz, .tar.bz2, .tar.zst and single .gz/.xz/.bz2/.zst files are recognised by their content. The argument after -x is taken as DIR unless it's a URL or an option, use --extract=DIR to be explicit.".white()
+    );
+    println!(
+        "{} {}",
+        "\n       --strip-components".white().bold(),
+        "N".white()
+    );
+    println!(
+        "{}",
+        "       Drops the first N path components of every archive entry, like tar does. Requires -x/--extract or --untar.".white()
+    );
+    println!("{}", "\n       --delete-archive".white().bold());
+    println!(
+        "{}",
+        "       Deletes the downloaded archive once it has been extracted. Requires -x/--extract."
+            .white()
+    );
+    println!(
+        "{} {}",
+        "\n       --quality".white().bold(),
+        "best|worst|audio|HEIGHT".white()
+    );
+    println!(
+        "{}",
+        "       Picks the video quality: best (default), worst, audio (audio only) or the highest video height, e.g. 720 or 1080p. Requires -m/--media.".white()
+    );
+    println!("{} {}", "\n       --subs".white().bold(), "[LANGS]".white());
+    println!(
+        "{}",
+        "       Saves subtitle files next to the video. LANGS is a comma separated list, e.g. en,de or all. Only two letter language codes are taken from the next argument, other lists like fil or yue need --subs=LANGS. Requires -m/--media.".white()
+    );
+    println!("{}", "\n       --auto-subs".white().bold());
+    println!(
+        "{}",
+        "       Saves automatically generated subtitles as well. Requires -m/--media.".white()
+    );
+    println!("{}", "\n       --embed-subs".white().bold());
+    println!(
+        "{}",
+        "       Embeds subtitles into the video file. Requires -m/--media.".white()
+    );
+    println!(
+        "{} {}",
+        "\n       --convert-subs".white().bold(),
+        "srt|vtt".white()
+    );
+    println!(
+        "{}",
+        "       Converts subtitle files to the given format using the bundled ffmpeg. Requires -m/--media.".white()
+    );
+    println!("{}", "\n       --thumbnail".white().bold());
+    println!(
+        "{}",
+        "       Saves the thumbnail image next to the video. Requires -m/--media.".white()
+    );
+    println!("{}", "\n       --info-json".white().bold());
+    println!(
+        "{}",
+        "       Saves video metadata to a .info.json file next to the video. Requires -m/--media."
+            .white()
+    );
+    println!(
+        "{} {} {} {}",
+        "\n       -o".white().bold(),
+        "or".white(),
+        "--output".white().bold(),
+        "TEMPLATE".white()
+    );
+    println!(
+        "{}",
+        "       Names downloaded media using a yt-dlp output template, e.g. '%(uploader)s/%(upload_date)s - %(title)s.%(ext)s'. Subdirectories are created as needed and OUTPUT is used as the base directory. Instead of a template you can also pass a plain file name as OUTPUT for single videos, its extension is kept by remuxing video (mp4, mkv, webm, mov, avi, flv) or extracting audio (mp3, m4a, opus, flac, wav, aac), other extensions are replaced by the one yt-dlp picks. Requires -m/--media.".white()
+    );
+    println!(
+        "{} {}",
+        "\n       --ytdlp-arg".white().bold(),
+        "ARG".white()
+    );
+    println!(
+        "{}",
+        "       Passes ARG straight to yt-dlp. Can be repeated. Everything after -- is passed to yt-dlp as well. Options controlled by pls, like --ffmpeg-location, -P and -o (use pls's own -o/--output), are refused. Requires -m/--media.".white()
+    );
+    println!(
+        "{} {}",
+        "\n       --deps".white().bold(),
+        "bundled|system|auto".white()
+    );
+    println!(
+        "{}",
+        "       Chooses where yt-dlp and ffmpeg come from. bundled always uses the copies pls installs, system uses the ones in PATH and auto (default) prefers PATH unless they are missing or too old. PLS_YTDLP and PLS_FFMPEG environment variables point pls to explicit binaries.".white()
+    );
+    println!(
+        "{} {} {}",
+        "\n       -q".white().bold(),
+        "or".white(),
+        "--quiet".white().bold()
+    );
+    println!(
+        "{}",
+        "       Prints only errors, no progress bar and no status messages. yt-dlp runs with --quiet.".white()
+    );
+    println!(
+        "{} {} {} {} {}",
+        "\n       -v".white().bold(),
+        "or".white(),
+        "--verbose".white().bold(),
+        "or".white(),
+        "-vv".white().bold()
+    );
+    println!(
+        "{}",
+        "       Prints the request, response status, redirects, the chosen file name and the yt-dlp and ffmpeg paths on stderr. -vv adds request and response headers.".white()
+    );
+    println!(
+        "{} {}",
+        "\n       --color".white().bold(),
+        "never|auto|always".white()
+    );
+    println!(
+        "{}",
+        "       Turns colours off or forces them. auto (default) colours terminals only and honours NO_COLOR. Output that isn't a terminal also gets plain lines instead of boxes.".white()
+    );
     println!(
         "{} {} {}",
         "\n       -v".white().bold(),
@@ -380,7 +657,7 @@ fn help() {
     );
     println!(
         "{}",
-        "       This option prints version of the program. Must be passed alone.".white()
+        "       This option prints version of the program. Must be passed alone, with other arguments -v means --verbose.".white()
     );
     println!(
         "{} {} {}",
@@ -403,869 +680,1189 @@ fn help() {
         "       This option force updates yt-dlp and ffmpeg binaries. Must be passed alone."
             .white()
     );
-}
-
-#[cfg(unix)]
-fn extract_tar_xz(file_path: &str, output_dir: &str) -> std::io::Result<()> {
-    // Open the .tar.xz file
-    let file = File::open(file_path)?;
-    let decompressor = XzDecoder::new(BufReader::new(file));
-
-    // Create the tar archive from the decompressed data
-    let mut archive = Archive::new(decompressor);
-
-    // Extract the archive to the specified output directory
-    archive.unpack(output_dir)?;
-    Ok(())
-}
-
-#[cfg(windows)]
-fn extract_zip(zip_path: &str, output_dir: &str) -> io::Result<()> {
-    // Open the zip file
-    let file = File::open(zip_path)?;
-    let mut archive = ZipArchive::new(BufReader::new(file))?;
-
-    // Iterate through the files in the zip archive
-    for i in 0..archive.len() {
-        let mut file = archive.by_index(i)?;
-        let outpath = std::path::Path::new(output_dir).join(file.name());
-
-        // Create directories if necessary
-        if file.name().ends_with('/') {
-            fs::create_dir_all(&outpath)?;
-        } else {
-            if let Some(parent) = outpath.parent() {
-                if !parent.exists() {
-                    fs::create_dir_all(parent)?;
-                }
-            }
-            // Write the file to the output directory
-            let mut outfile = File::create(&outpath)?;
-            io::copy(&mut file, &mut outfile)?;
-        }
-    }
-
-    Ok(())
-}
-
-#[cfg(unix)]
-fn run_command_interactive(command: &str) -> io::Result<()> {
-    let mut child = Command::new("bash")
-        .arg("-c")
-        .arg(command) // Pass the full command as a string
-        .stdout(Stdio::inherit()) // Inherit stdout for real-time output
-        .stderr(Stdio::inherit()) // Inherit stderr for real-time error output
-        .stdin(Stdio::inherit()) // Inherit stdin for interactivity
-        .spawn()?; // Spawn the process
-
-    child.wait()?; // Wait for the process to finish
-    Ok(())
-}
-
-#[cfg(windows)]
-fn run_command_interactive(command: &str) -> io::Result<()> {
-    // Launch the command in a Windows shell
-    let mut child = Command::new("cmd")
-        .args(["/C", command]) // Use cmd.exe and pass the command
-        .stdout(Stdio::inherit()) // Inherit stdout to show output in real-time
-        .stderr(Stdio::inherit()) // Inherit stderr to show error output in real-time
-        .stdin(Stdio::inherit()) // Inherit stdin for interactivity
-        .spawn()?; // Spawn the process
-
-    // Wait for the process to complete
-    child.wait()?;
-    Ok(())
-}
-
-#[cfg(unix)]
-fn ytdlp_install(
-    update: bool,
-    ytdlp_bin: PathBuf,
-    ytdlp_url: String,
-    ytdlp_zip: String,
-    libs: PathBuf,
-    termux: PathBuf,
-) -> io::Result<()> {
-    if ytdlp_bin.exists() {
-        fs::remove_file(&ytdlp_bin)?;
-    }
-    if update == true {
-        println!("{} {}", "Updating".white(), "yt-dlp".blue().bold());
-    } else {
-        println!("{} {}", "Installing".white(), "yt-dlp".blue().bold());
-    }
-
-    if termux.exists() && termux.is_dir() {
-        if libs.join("python3.12").exists() {
-            fs::remove_file(libs.join("python3.12"))?;
-        }
-        if libs.join("yt_dlp").exists() {
-            fs::remove_dir_all(libs.join("yt_dlp"))?;
-        }
-        go(ytdlp_url, ytdlp_zip.clone())?;
-        extract_tar_xz(&ytdlp_zip, &libs.to_string_lossy())?;
-        fs::remove_file(ytdlp_zip)?;
-    } else {
-        go(ytdlp_url, ytdlp_bin.to_string_lossy().to_string())?;
-        fs::set_permissions(&ytdlp_bin, Permissions::from_mode(0o755))?;
-    }
+    println!("{}", "\nCOMMANDS".white().bold());
+    println!("{}", "\n       pls self-update".white().bold());
+    println!(
+        "{}",
+        "       Replaces pls with the latest GitHub release for this platform after checking its SHA-256 checksum. The release has to publish a binary named pls-OS-ARCH (e.g. pls-linux-x86_64, pls-windows-x86_64.exe) and its checksum in a SHA256SUMS asset or in pls-OS-ARCH.sha256, releases without them aren't installed.".white()
+    );
+    println!(
+        "{} {}",
+        "\n       pls deps status".white().bold(),
+        "[yt-dlp|ffmpeg]".white()
+    );
+    println!(
+        "{}",
+        "       Shows path, version, size, source URL, install date and checksum of the bundled tools, and the copies found in PATH.".white()
+    );
+    println!(
+        "{} {}",
+        "\n       pls deps install".white().bold(),
+        "[yt-dlp|ffmpeg]".white()
+    );
+    println!(
+        "{}",
+        "       Installs the bundled tools that are missing.".white()
+    );
+    println!(
+        "{} {}",
+        "\n       pls deps update".white().bold(),
+        "[yt-dlp|ffmpeg]".white()
+    );
+    println!(
+        "{}",
+        "       Updates the bundled tools, a pinned yt-dlp is left alone.".white()
+    );
+    println!(
+        "{} {}",
+        "\n       pls deps pin".white().bold(),
+        "VERSION|latest".white()
+    );
+    println!(
+        "{}",
+        "       Installs a specific yt-dlp release tag and keeps it on updates. latest removes the pin. Needs a yt-dlp URL with {release}, mirrors hold a single build and can't be pinned.".white()
+    );
+    println!("{}", "\n       pls deps remove".white().bold());
+    println!(
+        "{}",
+        "       Removes everything pls has installed, the tools are downloaded again when needed."
+            .white()
+    );
+    println!(
+        "{} {}",
+        "\n       pls deps rollback".white().bold(),
+        "[yt-dlp|ffmpeg]".white()
+    );
+    println!(
+        "{}",
+        "       Updates keep the replaced yt-dlp and ffmpeg. This command swaps them back in, running it twice redoes the update.".white()
+    );
+    println!("{}", "\nFILES".white().bold());
+    println!("{}", "\n       config.toml".white().bold());
+    println!(
+        "{}",
+        "       Defaults in the pls config directory: output (directory used without OUTPUT, ~ is expanded), force, retries, headers (a table of names and values), proxy, quality, deps and the [theme] table. [profile.NAME] tables hold the same keys and are picked with --profile, their tables are merged into the defaults. Options on the command line override the file.".white()
+    );
+    println!("{}", "\nENVIRONMENT".white().bold());
+    println!("{}", "\n       PLS_DEPS_MANIFEST".white().bold());
+    println!(
+        "{}",
+        "       TOML file listing where yt-dlp and ffmpeg are downloaded from, defaults to deps.toml in the pls config directory. Entries are [[artifact]] tables with tool, os, arch (as Rust names it, or any), url and optional checksums, libc (gnu or musl) and requires (a program that must be in PATH).".white()
+    );
+    println!("{}", "\n       PLS_MIRROR".white().bold());
+    println!(
+        "{}",
+        "       Base URL or local directory holding copies of the tool downloads, overrides base in the manifest.".white()
+    );
+    println!("{}", "\n       PLS_EXTRACT_LIMIT".white().bold());
+    println!(
+        "{}",
+        "       Most GiB one archive may unpack to, 64 by default. Archives over it, with over a million entries or with paths leading outside the target directory are refused.".white()
+    );
+    println!("{}", "\n       PLS_UPDATE_DAYS".white().bold());
+    println!(
+        "{}",
+        "       Age in days after which media downloads look for a newer bundled yt-dlp and offer to update it, 14 by default and 0 turns it off.".white()
+    );
+    println!("{}", "\n       PLS_RELEASES_URL".white().bold());
+    println!(
+        "{}",
+        "       Release feed pls self-update reads instead of the GitHub API, a URL or a local JSON file.".white()
+    );
+    println!("{}", "\n       PLS_HOME".white().bold());
+    println!(
+        "{}",
+        "       Keeps everything pls writes in the data, cache and config folders of this directory, instead of the XDG_DATA_HOME, XDG_CACHE_HOME and XDG_CONFIG_HOME ones.".white()
+    );
+    println!("{}", "\n       NO_COLOR".white().bold());
+    println!(
+        "{}",
+        "       Turns colours off when set, unless --color always is passed.".white()
+    );
+}
 
-    if !ytdlp_bin.exists() {
-        if update == true {
-            println!(
-                "{} {} {} {}",
-                "Error:".red().bold(),
-                "Failed".white().bold(),
-                "to update".white(),
-                "yt-dlp".blue().bold()
-            );
-        } else {
-            println!(
-                "{} {} {} {}",
-                "Error:".red().bold(),
-                "Failed".white().bold(),
-                "to install".white(),
-                "yt-dlp".blue().bold()
-            );
-        }
-        return Ok(());
-    } else {
-        if update == true {
-            println!(
-                "{} {} {}",
-                "yt-dlp".blue().bold(),
-                "has been successfully".white(),
-                "updated.".white().bold()
-            );
-        } else {
-            println!(
-                "{} {} {}",
-                "yt-dlp".blue().bold(),
-                "has been successfully".white(),
-                "installed.".white().bold()
-            );
+// Returns the error lines the program printed, they are still shown as they come
+fn run_command_interactive(program: &Path, args: &[String]) -> io::Result<Vec<String>> {
+    let mut child = Command::new(program)
+        .args(args) // Pass every argument as is, no shell quoting involved
+        .stdout(Stdio::inherit()) // Inherit stdout for real-time output
+        .stderr(Stdio::piped()) // Pipe stderr to look for errors, echoing it line by line
+        .stdin(Stdio::inherit()) // Inherit stdin for interactivity
+        .spawn()?; // Spawn the process
+
+    let mut errors = Vec::new();
+    if let Some(stderr) = child.stderr.take() {
+        for line in BufReader::new(stderr).lines() {
+            let line = line?;
+            eprintln!("{}", line);
+            if line.starts_with("ERROR:") {
+                errors.push(line);
+            }
         }
     }
-    Ok(())
+    child.wait()?; // Wait for the process to finish
+    Ok(errors)
 }
 
-#[cfg(unix)]
-fn ffmpeg_install(
-    update: bool,
-    ffmpeg_bin: PathBuf,
-    ffprobe_bin: PathBuf,
-    ffplay_bin: PathBuf,
-    ffmpeg_url: String,
-    ffmpeg_zip: PathBuf,
-    termux: PathBuf,
-    architecture: &str,
-) -> io::Result<()> {
-    if ffmpeg_bin.exists() {
-        fs::remove_file(&ffmpeg_bin)?;
-    }
-    if ffprobe_bin.exists() {
-        fs::remove_file(&ffprobe_bin)?;
-    }
-    if ffplay_bin.exists() {
-        fs::remove_file(&ffplay_bin)?;
-    }
-    if update == true {
-        println!("{} {}", "Updating".white(), "ffmpeg".blue().bold());
-    } else {
-        println!("{} {}", "Installing".white(), "ffmpeg".blue().bold());
+// Connection settings plain and media downloads share
+#[derive(Default, Clone)]
+struct Network {
+    retries: Option<u32>,
+    headers: Vec<(String, String)>,
+    proxy: Option<String>,
+}
+
+#[derive(Default)]
+struct FileOptions {
+    force: bool,
+    network: Network,
+    stdout: bool,
+    decompress: bool,
+    untar: Option<String>,
+    extract: bool,
+    extract_dir: Option<String>,
+    strip_components: usize,
+    delete_archive: bool,
+}
+
+impl FileOptions {
+    fn is_empty(&self) -> bool {
+        !self.stdout
+            && !self.decompress
+            && self.untar.is_none()
+            && !self.extract
+            && self.strip_components == 0
+            && !self.delete_archive
     }
+}
 
-    go(ffmpeg_url, ffmpeg_zip.to_string_lossy().to_string())?;
-    if termux.exists() && termux.is_dir() {
-        extract_tar_xz(
-            &ffmpeg_zip.to_string_lossy(),
-            "/data/data/com.termux/files/usr/tmp",
-        )?;
-    } else {
-        extract_tar_xz(&ffmpeg_zip.to_string_lossy(), "/tmp")?;
+#[derive(Default)]
+struct MediaOptions {
+    subs: Option<String>,
+    auto_subs: bool,
+    embed_subs: bool,
+    thumbnail: bool,
+    info_json: bool,
+    convert_subs: Option<String>,
+    output_template: Option<String>,
+    no_playlist: bool,
+    quality: Option<String>,
+    // Extension of a plain OUTPUT file name, e.g. mp4 for video.mp4
+    file_format: Option<String>,
+    network: Network,
+    extra_args: Vec<String>,
+}
+
+impl MediaOptions {
+    fn is_empty(&self) -> bool {
+        self.subs.is_none()
+            && !self.auto_subs
+            && !self.embed_subs
+            && !self.thumbnail
+            && !self.info_json
+            && self.convert_subs.is_none()
+            && self.output_template.is_none()
+            && self.quality.is_none()
+            && self.extra_args.is_empty()
     }
-    fs::remove_file(ffmpeg_zip)?;
-    let source;
-    let source2;
-    let source3;
-    if termux.exists() && termux.is_dir() {
-        if architecture == "aarch64" {
-            source = "/data/data/com.termux/files/usr/tmp/ffmpeg-master-latest-linuxarm64-gpl/bin/ffmpeg";
-            source2 = "/data/data/com.termux/files/usr/tmp/ffmpeg-master-latest-linuxarm64-gpl/bin/ffprobe";
-            source3 = "/data/data/com.termux/files/usr/tmp/ffmpeg-master-latest-linuxarm64-gpl/bin/ffplay";
-        } else {
-            source =
-                "/data/data/com.termux/files/usr/tmp/ffmpeg-master-latest-linux64-gpl/bin/ffmpeg";
-            source2 =
-                "/data/data/com.termux/files/usr/tmp/ffmpeg-master-latest-linux64-gpl/bin/ffprobe";
-            source3 =
-                "/data/data/com.termux/files/usr/tmp/ffmpeg-master-latest-linux64-gpl/bin/ffplay";
+
+    // Translates the media options into yt-dlp arguments
+    fn ytdlp_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(template) = &self.output_template {
+            args.push("-o".to_string());
+            args.push(template.clone());
         }
-    } else {
-        if architecture == "aarch64" {
-            source = "/tmp/ffmpeg-master-latest-linuxarm64-gpl/bin/ffmpeg";
-            source2 = "/tmp/ffmpeg-master-latest-linuxarm64-gpl/bin/ffprobe";
-            source3 = "/tmp/ffmpeg-master-latest-linuxarm64-gpl/bin/ffplay";
-        } else {
-            source = "/tmp/ffmpeg-master-latest-linux64-gpl/bin/ffmpeg";
-            source2 = "/tmp/ffmpeg-master-latest-linux64-gpl/bin/ffprobe";
-            source3 = "/tmp/ffmpeg-master-latest-linux64-gpl/bin/ffplay";
+        if self.no_playlist {
+            args.push("--no-playlist".to_string());
         }
-    }
-    fs::copy(source, &ffmpeg_bin)?;
-    fs::copy(source2, &ffprobe_bin)?;
-    fs::copy(source3, &ffplay_bin)?;
-    if termux.exists() && termux.is_dir() {
-        if architecture == "aarch64" {
-            fs::remove_dir_all(
-                "/data/data/com.termux/files/usr/tmp/ffmpeg-master-latest-linuxarm64-gpl",
-            )?;
-        } else {
-            fs::remove_dir_all(
-                "/data/data/com.termux/files/usr/tmp/ffmpeg-master-latest-linux64-gpl",
-            )?;
+        if let Some(langs) = &self.subs {
+            args.push("--write-subs".to_string());
+            if !langs.is_empty() {
+                args.push("--sub-langs".to_string());
+                args.push(langs.clone());
+            }
         }
-    } else {
-        if architecture == "aarch64" {
-            fs::remove_dir_all("/tmp/ffmpeg-master-latest-linuxarm64-gpl")?;
-        } else {
-            fs::remove_dir_all("/tmp/ffmpeg-master-latest-linux64-gpl")?;
+        if self.auto_subs {
+            args.push("--write-auto-subs".to_string());
         }
-    }
-    fs::set_permissions(&ffmpeg_bin, Permissions::from_mode(0o755))?;
-    fs::set_permissions(&ffprobe_bin, Permissions::from_mode(0o755))?;
-    fs::set_permissions(&ffplay_bin, Permissions::from_mode(0o755))?;
-
-    if !ffmpeg_bin.exists() || !ffprobe_bin.exists() || !ffplay_bin.exists() {
-        if update == true {
-            println!(
-                "{} {} {} {}",
-                "Error:".red().bold(),
-                "Failed".white().bold(),
-                "to update".white(),
-                "ffmpeg".blue().bold()
-            );
-        } else {
-            println!(
-                "{} {} {} {}",
-                "Error:".red().bold(),
-                "Failed".white().bold(),
-                "to install".white(),
-                "ffmpeg".blue().bold()
-            );
+        if self.embed_subs {
+            args.push("--embed-subs".to_string());
         }
-        return Ok(());
-    } else {
-        if update == true {
-            println!(
-                "{} {} {}",
-                "ffmpeg".blue().bold(),
-                "has been successfully".white(),
-                "updated.".white().bold()
-            );
-        } else {
-            println!(
-                "{} {} {}",
-                "ffmpeg".blue().bold(),
-                "has been successfully".white(),
-                "installed.".white().bold()
-            );
+        if self.thumbnail {
+            args.push("--write-thumbnail".to_string());
+        }
+        if self.info_json {
+            args.push("--write-info-json".to_string());
+        }
+        if let Some(format) = &self.convert_subs {
+            args.push("--convert-subs".to_string());
+            args.push(format.clone());
+        }
+        if let Some(quality) = self.quality.as_deref().and_then(quality_args) {
+            args.extend(quality);
         }
+        if let Some(format) = &self.file_format {
+            args.extend(format_args(format));
+        }
+        for (name, value) in &self.network.headers {
+            args.push("--add-headers".to_string());
+            args.push(format!("{}:{}", name, value));
+        }
+        if let Some(proxy) = &self.network.proxy {
+            args.push("--proxy".to_string());
+            args.push(proxy.clone());
+        }
+        if let Some(retries) = self.network.retries {
+            args.push("--retries".to_string());
+            args.push(retries.to_string());
+        }
+        args.extend(self.extra_args.iter().cloned());
+        args
     }
-    Ok(())
 }
 
-#[cfg(unix)]
-fn ytdlp_check(update: bool) -> io::Result<()> {
-    if let Some(home) = home_dir() {
-        let architecture = std::env::consts::ARCH;
-        let libs = home.join(".local/share/pls/libs");
-        let ytdlp_bin = libs.join("yt-dlp");
-        let ytdlp_url;
-        let mut ytdlp_zip = String::new();
-        let termux = PathBuf::from(home.join(".termux"));
-        if termux.exists() && termux.is_dir() {
-            if architecture == "aarch64" {
-                ytdlp_url = "https://storage.googleapis.com/mochov-public/pls/aarch64/yt-dlp-aarch64.tar.xz".to_string();
-                ytdlp_zip = "/data/data/com.termux/files/usr/tmp/yt-dlp-aarch64.tar.xz".to_string();
-            } else {
-                ytdlp_url =
-                    "https://storage.googleapis.com/mochov-public/pls/amd64/yt-dlp-amd64.tar.xz"
-                        .to_string();
-                ytdlp_zip = "/data/data/com.termux/files/usr/tmp/yt-dlp-amd64.tar.xz".to_string();
-            }
-        } else {
-            if architecture == "aarch64" {
-                ytdlp_url =
-                            "https://github.com/yt-dlp/yt-dlp/releases/latest/download/yt-dlp_linux_aarch64"
-                                .to_string();
-            } else {
-                ytdlp_url =
-                    "https://github.com/yt-dlp/yt-dlp/releases/latest/download/yt-dlp_linux"
-                        .to_string();
-            }
+// best, worst, audio or the highest video height like 720 or 1080p, as a yt-dlp format
+fn quality_args(quality: &str) -> Option<Vec<String>> {
+    let format = match quality {
+        "best" => return Some(Vec::new()),
+        "worst" => "wv*+wa/w".to_string(),
+        "audio" => return Some(vec!["-f".into(), "ba/b".into(), "-x".into()]),
+        height => {
+            let height: u32 = height.strip_suffix('p').unwrap_or(height).parse().ok()?;
+            format!("bv*[height<={0}]+ba/b[height<={0}]", height)
         }
+    };
+    Some(vec!["-f".to_string(), format])
+}
 
-        let ffmpeg_bin = libs.join("ffmpeg");
-        let ffprobe_bin = libs.join("ffprobe");
-        let ffplay_bin = libs.join("ffplay");
-        let ffmpeg_zip;
-        if termux.exists() && termux.is_dir() {
-            if architecture == "aarch64" {
-                ffmpeg_zip = PathBuf::from("/data/data/com.termux/files/usr/tmp/ffmpeg-master-latest-linuxarm64-gpl.tar.xz")
-            } else {
-                ffmpeg_zip = PathBuf::from(
-                    "/data/data/com.termux/files/usr/tmp/ffmpeg-master-latest-linux64-gpl.tar.xz",
-                );
-            }
-        } else {
-            if architecture == "aarch64" {
-                ffmpeg_zip = PathBuf::from("/tmp/ffmpeg-master-latest-linuxarm64-gpl.tar.xz");
-            } else {
-                ffmpeg_zip = PathBuf::from("/tmp/ffmpeg-master-latest-linux64-gpl.tar.xz");
-            }
+// Makes the file end up with the extension of OUTPUT: video containers are remuxed, audio
+// formats extracted. yt-dlp keeps its own choice for anything else.
+fn format_args(ext: &str) -> Vec<String> {
+    match ext {
+        "mp4" | "mkv" | "webm" | "mov" | "avi" | "flv" => {
+            vec!["--remux-video".to_string(), ext.to_string()]
         }
+        "mp3" | "m4a" | "opus" | "flac" | "wav" | "aac" => vec![
+            "-x".to_string(),
+            "--audio-format".to_string(),
+            ext.to_string(),
+        ],
+        _ => Vec::new(),
+    }
+}
 
-        let ffmpeg_url;
-        if termux.exists() && termux.is_dir() {
-            if architecture == "aarch64" {
-                ffmpeg_url = "https://storage.googleapis.com/mochov-public/pls/aarch64/ffmpeg-master-latest-linuxarm64-gpl.tar.xz".to_string();
-            } else {
-                ffmpeg_url =
-                "https://storage.googleapis.com/mochov-public/pls/amd64/ffmpeg-master-latest-linux64-gpl.tar.xz".to_string();
-            }
-        } else {
-            if architecture == "aarch64" {
-                ffmpeg_url = "https://github.com/BtbN/FFmpeg-Builds/releases/download/latest/ffmpeg-master-latest-linuxarm64-gpl.tar.xz".to_string();
-            } else {
-                ffmpeg_url = "https://github.com/BtbN/FFmpeg-Builds/releases/download/latest/ffmpeg-master-latest-linux64-gpl.tar.xz".to_string();
-            }
-        }
+// Splits "Name: value" like curl's -H, the parts must make a valid HTTP header
+fn parse_header(header: &str) -> Option<(String, String)> {
+    let (name, value) = header.split_once(':')?;
+    let (name, value) = (name.trim(), value.trim());
+    valid_header(name, value).then(|| (name.to_string(), value.to_string()))
+}
 
-        if !libs.exists() {
-            std::fs::create_dir_all(&libs)?;
-        }
-        if !ytdlp_bin.exists()
-            || update == true
-            || ((!libs.join("python3.12").exists() || !libs.join("yt_dlp").exists())
-                && termux.exists())
-        {
-            ytdlp_install(
-                update,
-                ytdlp_bin,
-                ytdlp_url,
-                ytdlp_zip,
-                libs,
-                termux.clone(),
-            )?;
-        }
-        if !ffmpeg_bin.exists() || !ffplay_bin.exists() || !ffprobe_bin.exists() || update == true {
-            ffmpeg_install(
-                update,
-                ffmpeg_bin,
-                ffprobe_bin,
-                ffplay_bin,
-                ffmpeg_url,
-                ffmpeg_zip,
-                termux,
-                architecture,
-            )?;
-        }
+fn valid_header(name: &str, value: &str) -> bool {
+    HeaderName::from_bytes(name.as_bytes()).is_ok() && HeaderValue::from_str(value).is_ok()
+}
+
+// Options pls sets on its own, passing them through would break the download. -o comes from
+// pls's own -o/--output or the OUTPUT file name.
+const YTDLP_DENYLIST: [&str; 5] = ["--ffmpeg-location", "-P", "--paths", "-o", "--output"];
+
+fn denied_ytdlp_arg(arg: &str) -> Option<&'static str> {
+    YTDLP_DENYLIST.into_iter().find(|denied| {
+        arg == *denied
+            || arg.starts_with(&format!("{}=", denied))
+            || (!denied.starts_with("--") && arg.starts_with(denied))
+    })
+}
+
+fn denied_ytdlp_arg_error(option: &str) -> bool {
+    output::option_error(
+        option,
+        "is controlled by pls and can't be passed to yt-dlp!",
+    );
+    true
+}
+
+// The argument after -x is its directory unless it's an option or the URL
+fn looks_like_extract_dir(arg: &str) -> bool {
+    !arg.starts_with('-') && !arg.contains("://")
+}
+
+// ISO 639-1 codes, anything else after --subs is taken for OUTPUT unless passed as --subs=LANGS
+const LANGUAGE_CODES: [&str; 184] = [
+    "aa", "ab", "ae", "af", "ak", "am", "an", "ar", "as", "av", "ay", "az", "ba", "be", "bg", "bh",
+    "bi", "bm", "bn", "bo", "br", "bs", "ca", "ce", "ch", "co", "cr", "cs", "cu", "cv", "cy", "da",
+    "de", "dv", "dz", "ee", "el", "en", "eo", "es", "et", "eu", "fa", "ff", "fi", "fj", "fo", "fr",
+    "fy", "ga", "gd", "gl", "gn", "gu", "gv", "ha", "he", "hi", "ho", "hr", "ht", "hu", "hy", "hz",
+    "ia", "id", "ie", "ig", "ii", "ik", "io", "is", "it", "iu", "ja", "jv", "ka", "kg", "ki", "kj",
+    "kk", "kl", "km", "kn", "ko", "kr", "ks", "ku", "kv", "kw", "ky", "la", "lb", "lg", "li", "ln",
+    "lo", "lt", "lu", "lv", "mg", "mh", "mi", "mk", "ml", "mn", "mr", "ms", "mt", "my", "na", "nb",
+    "nd", "ne", "ng", "nl", "nn", "no", "nr", "nv", "ny", "oc", "oj", "om", "or", "os", "pa", "pi",
+    "pl", "ps", "pt", "qu", "rm", "rn", "ro", "ru", "rw", "sa", "sc", "sd", "se", "sg", "si", "sk",
+    "sl", "sm", "sn", "so", "sq", "sr", "ss", "st", "su", "sv", "sw", "ta", "te", "tg", "th", "ti",
+    "tk", "tl", "tn", "to", "tr", "ts", "tt", "tw", "ty", "ug", "uk", "ur", "uz", "ve", "vi", "vo",
+    "wa", "wo", "xh", "yi", "yo", "za", "zh", "zu",
+];
+
+// Decides whether the argument after --subs is a language list like "en,de" or "pt-BR,en.*"
+fn looks_like_sub_langs(arg: &str) -> bool {
+    if arg.starts_with('-') || arg.contains("://") || Path::new(arg).exists() {
+        return false;
     }
+    arg.split(',').all(|lang| {
+        let code = lang.split(['-', '.', '_']).next().unwrap_or_default();
+        let rest_ok = lang
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '*'));
+        rest_ok && (code == "all" || LANGUAGE_CODES.contains(&code))
+    })
+}
 
-    Ok(())
+// Turns a plain output file name into a yt-dlp output template, the extension is added by
+// yt-dlp once format_args() made it convert to the one asked for
+fn media_file_template(file_name: &str) -> String {
+    if file_name.contains("%(") {
+        return file_name.to_string();
+    }
+    let stem = Path::new(file_name)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| file_name.to_string());
+    format!("{}.%(ext)s", stem.replace('%', "%%"))
 }
 
-#[cfg(windows)]
-fn ytdlp_install(update: bool, ytdlp_bin: PathBuf, ytdlp_url: String) -> io::Result<()> {
-    if ytdlp_bin.exists() {
-        fs::remove_file(&ytdlp_bin)?;
+// Downloads a single video into an explicit output file name or template
+fn ytdlp_file_go(
+    deps_mode: DepsMode,
+    url: String,
+    out: String,
+    force: bool,
+    mut media_options: MediaOptions,
+) -> io::Result<()> {
+    if media_options.output_template.is_some() {
+        output::option_error("-o/--output", "can't be combined with an output file name.");
+        return Ok(());
     }
-    if update == true {
-        println!("{} {}", "Updating".white(), "yt-dlp".blue().bold());
-    } else {
-        println!("{} {}", "Installing".white(), "yt-dlp".blue().bold());
+    if out.contains("%(") {
+        media_options.output_template = Some(out);
+        return ytdlp_go(deps_mode, url, ".".to_string(), force, &media_options);
+    }
+    if Path::new(&out).is_file() && !force {
+        output::error("exists", "File already exists!");
+        return Ok(());
     }
-    go(ytdlp_url, ytdlp_bin.to_string_lossy().to_string())?;
 
-    if !ytdlp_bin.exists() {
-        if update == true {
-            println!(
-                "{} {} {} {}",
-                "Error:".red().bold(),
-                "Failed".white().bold(),
-                "to update".white(),
-                "yt-dlp".blue().bold()
-            );
-        } else {
-            println!(
-                "{} {} {} {}",
-                "Error:".red().bold(),
-                "Failed".white().bold(),
-                "to install".white(),
-                "yt-dlp".blue().bold()
-            );
-        }
+    let mut out_dir = get_dir_from_path(&out);
+    if out_dir.is_empty() {
+        out_dir = ".".to_string();
+    }
+    if !Path::new(&out_dir).is_dir() {
+        output::error("not_found", "Output directory couldn't be found!");
         return Ok(());
-    } else {
-        if update == true {
-            println!(
-                "{} {} {}",
-                "yt-dlp".blue().bold(),
-                "has been successfully".white(),
-                "updated.".white().bold()
-            );
-        } else {
-            println!(
-                "{} {} {}",
-                "yt-dlp".blue().bold(),
-                "has been successfully".white(),
-                "installed.".white().bold()
-            );
-        }
     }
-    Ok(())
+    let file_name = Path::new(&out)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    media_options.output_template = Some(media_file_template(&file_name));
+    media_options.file_format = Path::new(&file_name)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    media_options.no_playlist = true;
+    ytdlp_go(deps_mode, url, out_dir, force, &media_options)
 }
 
-#[cfg(windows)]
-fn ffmpeg_install(
-    update: bool,
-    ffmpeg_bin: PathBuf,
-    ffprobe_bin: PathBuf,
-    ffplay_bin: PathBuf,
-    ffmpeg_url: String,
-    ffmpeg_zip: PathBuf,
-    home: PathBuf,
+fn ytdlp_go(
+    deps_mode: DepsMode,
+    url: String,
+    out: String,
+    force: bool,
+    media_options: &MediaOptions,
 ) -> io::Result<()> {
-    if update == true {
-        println!("{} {}", "Updating".white(), "ffmpeg".blue().bold());
+    let Some(deps) = deps::resolve(deps_mode)? else {
+        return Ok(());
+    };
+    output::verbose(1, &format!("* yt-dlp: {}", deps.ytdlp.to_string_lossy()));
+    output::verbose(1, &format!("* ffmpeg: {}", deps.ffmpeg.to_string_lossy()));
+    let mut args = vec![url];
+    if force {
+        args.push("--force-overwrites".to_string());
     } else {
-        println!("{} {}", "Installing".white(), "ffmpeg".blue().bold());
-    }
-    if ffmpeg_bin.exists() {
-        fs::remove_file(&ffmpeg_bin)?;
+        args.push("--no-overwrites".to_string());
     }
-    if ffprobe_bin.exists() {
-        fs::remove_file(&ffprobe_bin)?;
+    args.push("--ffmpeg-location".to_string());
+    args.push(deps.ffmpeg.to_string_lossy().to_string());
+    args.push("-P".to_string());
+    args.push(out);
+    if output::quiet() {
+        args.push("--quiet".to_string());
     }
-    if ffplay_bin.exists() {
-        fs::remove_file(&ffplay_bin)?;
-    }
-    go(ffmpeg_url, ffmpeg_zip.to_string_lossy().to_string())?;
-    extract_zip(
-        &ffmpeg_zip.to_string_lossy(),
-        &home.join("AppData\\Local\\Temp").to_string_lossy(),
-    )?;
-    fs::remove_file(ffmpeg_zip)?;
-    let source = "AppData\\Local\\Temp\\ffmpeg-7.1-essentials_build\\bin\\ffmpeg.exe";
-    let source2 = "AppData\\Local\\Temp\\ffmpeg-7.1-essentials_build\\bin\\ffprobe.exe";
-    let source3 = "AppData\\Local\\Temp\\ffmpeg-7.1-essentials_build\\bin\\ffplay.exe";
-    fs::copy(source, &ffmpeg_bin)?;
-    fs::copy(source2, &ffprobe_bin)?;
-    fs::copy(source3, &ffplay_bin)?;
-    fs::remove_dir_all(home.join("AppData\\Local\\Temp\\ffmpeg-7.1-essentials_build"))?;
-
-    if !ffmpeg_bin.exists() || !ffplay_bin.exists() || !ffprobe_bin.exists() {
-        if update == true {
-            println!(
-                "{} {} {} {}",
-                "Error:".red().bold(),
-                "Failed".white().bold(),
-                "to update".white(),
-                "ffmpeg".blue().bold()
-            );
-        } else {
-            println!(
-                "{} {} {} {}",
-                "Error:".red().bold(),
-                "Failed".white().bold(),
-                "to install".white(),
-                "ffmpeg".blue().bold()
-            );
-        }
-        return Ok(());
+    args.extend(media_options.ytdlp_args());
+    // Quiet runs are often unattended, nobody would answer the update prompt
+    let update_check = if output::quiet() {
+        None
     } else {
-        if update == true {
-            println!(
-                "{} {} {}",
-                "ffmpeg".blue().bold(),
-                "has been successfully".white(),
-                "updated.".white().bold()
-            );
-        } else {
+        deps::start_update_check(&deps)
+    };
+    let errors = run_command_interactive(&deps.ytdlp, &args)?;
+
+    // Site changes break extractors all the time, a fresh yt-dlp usually fixes them
+    if errors.iter().any(|line| deps::is_extractor_error(line))
+        && deps::ytdlp_updatable(&deps)
+        && deps::newer_ytdlp_available(&deps)
+    {
+        if !output::quiet() {
             println!(
-                "{} {} {}",
-                "ffmpeg".blue().bold(),
-                "has been successfully".white(),
-                "installed.".white().bold()
+                "{} {}",
+                "yt-dlp".blue().bold(),
+                "failed to extract, updating it and trying again.".white()
             );
         }
+        deps::ytdlp_check(true, &[Tool::Ytdlp])?;
+        run_command_interactive(&deps.ytdlp, &args)?;
+    } else if let Some(check) = update_check {
+        deps::offer_update(check)?;
     }
     Ok(())
 }
 
-#[cfg(windows)]
-fn ytdlp_check(update: bool) -> io::Result<()> {
-    if let Some(home) = home_dir() {
-        let libs = home.join("AppData\\Roaming\\pls\\libs");
-        let ytdlp_bin = libs.join("yt-dlp.exe");
-        let ytdlp_url =
-            "https://github.com/yt-dlp/yt-dlp/releases/latest/download/yt-dlp.exe".to_string();
-        let ffmpeg_bin = libs.join("ffmpeg.exe");
-        let ffprobe_bin = libs.join("ffprobe.exe");
-        let ffplay_bin = libs.join("ffplay.exe");
-        let ffmpeg_zip = home.join("AppData\\Local\\Temp\\ffmpeg-release-essentials.zip");
-        let ffmpeg_url =
-            "https://www.gyan.dev/ffmpeg/builds/ffmpeg-release-essentials.zip".to_string();
-
-        if !libs.exists() {
-            std::fs::create_dir_all(libs)?;
-        }
-        if !ytdlp_bin.exists() || update == true {
-            ytdlp_install(update, ytdlp_bin, ytdlp_url)?;
-        }
-        if !ffmpeg_bin.exists() || !ffplay_bin.exists() || !ffprobe_bin.exists() || update == true {
-            ffmpeg_install(
-                update,
-                ffmpeg_bin,
-                ffprobe_bin,
-                ffplay_bin,
-                ffmpeg_url,
-                ffmpeg_zip,
-                home,
-            )?;
-        }
-    }
-
-    Ok(())
-}
+fn main() -> io::Result<()> {
+    let cli = Cli::parse();
+    let command = cli.args.first().cloned().unwrap_or_default();
+    output::init_color();
 
-fn ytdlp_go(system: String, url: String, out: String, force: bool) -> io::Result<()> {
-    if let Some(home) = home_dir() {
-        if system == "unix" {
-            let libs = home.join(".local/share/pls/libs");
-            let ytdlp_bin = libs.join("yt-dlp");
-            if force == true {
-                let dw = format!(
-                    "{} {} --force-overwrites --ffmpeg-location {} -P {}",
-                    ytdlp_bin.to_string_lossy(),
-                    url,
-                    libs.to_string_lossy(),
-                    out
-                );
-                run_command_interactive(&dw)?;
-            } else {
-                let dw = format!(
-                    "{} {} --no-overwrites --ffmpeg-location {} -P {}",
-                    ytdlp_bin.to_string_lossy(),
-                    url,
-                    libs.to_string_lossy(),
-                    out
-                );
-                run_command_interactive(&dw)?;
-            }
-        } else {
-            let libs = home.join("AppData\\Roaming\\pls\\libs");
-            let ytdlp_bin = libs.join("yt-dlp.exe");
-            if force == true {
-                let dw = format!(
-                    "{} {} --force-overwrites --ffmpeg-location {} -P {}",
-                    ytdlp_bin.to_string_lossy(),
-                    url,
-                    libs.to_string_lossy(),
-                    out
-                );
-                run_command_interactive(&dw)?;
-            } else {
-                let dw = format!(
-                    "{} {} --no-overwrites --ffmpeg-location {} -P {}",
-                    ytdlp_bin.to_string_lossy(),
-                    url,
-                    libs.to_string_lossy(),
-                    out
-                );
-                run_command_interactive(&dw)?;
-            }
-        }
-    } else {
+    if command.is_empty() {
+        help();
+    } else if command == "--version" || (command == "-v" && cli.args.len() == 1) {
+        let version = env!("CARGO_PKG_VERSION");
         println!(
             "{} {} {}",
-            "Error:".red().bold(),
-            "Failed".white().bold(),
-            "to determine home directory.".white()
+            "pls,".white().bold(),
+            "version -".white().bold(),
+            version.blue().bold()
         );
-        return Ok(());
-    }
-    Ok(())
-}
-
-fn main() -> io::Result<()> {
-    let cli = Cli::parse();
-
-    if cli.command.is_empty() {
+    } else if command == "--help" || command == "-h" {
         help();
+    } else if command == "--update" || command == "-u" {
+        deps::ytdlp_check(true, &[Tool::Ytdlp, Tool::Ffmpeg])?;
+    } else if command == "self-update" {
+        self_update::self_update()?;
+    } else if command == "deps" {
+        deps::deps_command(&cli.args[1..])?;
     } else {
-        let cmd = cli.command;
         let mut url = "empty".to_string();
         let mut out = "empty".to_string();
         let mut force = false;
         let mut media = false;
         let mut illegal = false;
-        let mut update = false;
+        let mut media_options = MediaOptions::default();
+        let mut file_options = FileOptions::default();
+        let mut deps_arg = None;
         let mut file_name = String::new();
+        let mut positional = Vec::new();
+        let mut output_arg = None;
+        let mut verbosity: Option<u8> = None;
+        let mut theme_arg = None;
+        let mut profile_arg = None;
+        let mut no_force = false;
+        let mut retries_arg = None;
+        let mut header_args = Vec::new();
+        let mut proxy_arg = None;
         let system = detect_os();
-        let version = "0.1.8".to_string();
 
-        if cmd != "-f" && cmd != "--force" {
-            if cmd == "-m" || cmd == "--media" {
-                media = true;
-            } else {
-                url = cmd;
-            }
-        } else {
-            force = true;
+        // --json is looked for up front, so even argument errors come out as events
+        let json = cli
+            .args
+            .iter()
+            .take_while(|arg| *arg != "--")
+            .any(|arg| arg == "--json");
+        if json {
+            output::set_json();
+        }
+        // So is stdout mode, no error may end up in the data piped on
+        if cli
+            .args
+            .iter()
+            .take_while(|arg| *arg != "--")
+            .any(|arg| arg == "-" || arg == "--stdout" || arg == "-O=-")
+        {
+            output::set_stdout_data();
         }
 
-        if url == "--version" || url == "-v" {
-            println!(
-                "{} {} {}",
-                "pls,".white().bold(),
-                "version -".white().bold(),
-                version.blue().bold()
-            );
-        } else if url == "--help" || url == "-h" {
-            help();
-        } else if url == "--update" || url == "-u" {
-            update = true;
-            ytdlp_check(update)?;
-        } else {
-            for (i, arg) in cli.args.iter().enumerate() {
-                if i == 0 {
-                    if force == true {
-                        if arg == "-f" || arg == "--force" {
-                            illegal = double_force();
-                        } else if arg == "-m" || arg == "--media" {
-                            media = true;
-                        } else {
-                            url = arg.clone();
-                        }
-                    } else if media == true {
-                        if arg == "-m" || arg == "--media" {
-                            illegal = double_media();
-                        } else if arg == "-f" || arg == "--force" {
-                            force = true;
-                        } else {
-                            url = arg.clone();
-                        }
-                    } else if arg == "-f" || arg == "--force" {
-                        force = true;
-                    } else if arg == "-m" || arg == "--media" {
-                        media = true;
-                    } else {
-                        out = arg.clone();
+        let mut args = cli.args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            if arg == "-f" || arg == "--force" {
+                if force {
+                    illegal = double_force();
+                } else {
+                    force = true;
+                }
+            } else if arg == "-m" || arg == "--media" {
+                if media {
+                    illegal = double_media();
+                } else {
+                    media = true;
+                }
+            } else if arg == "--subs" || arg.starts_with("--subs=") {
+                if let Some(langs) = arg.strip_prefix("--subs=") {
+                    media_options.subs = Some(langs.to_string());
+                } else if args.peek().is_some_and(|next| looks_like_sub_langs(next)) {
+                    media_options.subs = args.next();
+                } else {
+                    media_options.subs = Some(String::new());
+                }
+            } else if arg == "-x" || arg == "--extract" || arg.starts_with("--extract=") {
+                file_options.extract = true;
+                if let Some(dir) = arg.strip_prefix("--extract=") {
+                    file_options.extract_dir = Some(dir.to_string());
+                } else if args.peek().is_some_and(|next| looks_like_extract_dir(next)) {
+                    file_options.extract_dir = args.next();
+                }
+            } else if arg == "--strip-components" || arg.starts_with("--strip-components=") {
+                let count = match arg.strip_prefix("--strip-components=") {
+                    Some(count) => Some(count.to_string()),
+                    None => args.next(),
+                };
+                match count.as_deref().map(str::parse::<usize>) {
+                    Some(Ok(count)) => file_options.strip_components = count,
+                    _ => {
+                        output::option_error("--strip-components", "expects a number!");
+                        illegal = true;
                     }
-                } else if i == 1 {
-                    if force == true {
-                        if arg == "-f" || arg == "--force" {
-                            illegal = double_force();
-                        } else {
-                            if media == true {
-                                if arg == "-m" || arg == "--media" {
-                                    illegal = double_media();
-                                } else {
-                                    url = arg.clone();
-                                }
-                            } else {
-                                if arg == "-m" || arg == "--media" {
-                                    media = true;
-                                } else {
-                                    out = arg.clone();
-                                }
-                            }
-                        }
-                    } else if media == true {
-                        if arg == "-m" || arg == "--media" {
-                            illegal = double_force();
-                        } else {
-                            if arg == "-f" || arg == "--force" {
-                                force = true;
-                            } else {
-                                out = arg.clone();
-                            }
-                        }
-                    } else if arg == "-f" || arg == "--force" {
-                        force = true;
-                    } else if arg == "-m" || arg == "--media" {
-                        media = true;
-                    } else {
-                        println!("{} {}", "Error:".red().bold(), "bad arguments!".white());
+                }
+            } else if arg == "--delete-archive" {
+                file_options.delete_archive = true;
+            } else if arg == "-q"
+                || arg == "--quiet"
+                || arg == "-v"
+                || arg == "--verbose"
+                || arg == "-vv"
+            {
+                let level = match arg.as_str() {
+                    "-q" | "--quiet" => 0,
+                    "-vv" => 3,
+                    _ => 2,
+                };
+                // Only quiet clashes, of -v and -vv the more verbose one wins
+                if verbosity.is_some_and(|set| (set == 0) != (level == 0)) {
+                    output::error("usage", "-q/--quiet and -v/--verbose can't be combined!");
+                    illegal = true;
+                }
+                let level = verbosity.map_or(level, |set| set.max(level));
+                verbosity = Some(level);
+                output::set_verbosity(level);
+            } else if arg == "--color" || arg.starts_with("--color=") {
+                let choice = match arg.strip_prefix("--color=") {
+                    Some(choice) => Some(choice.to_string()),
+                    None => args.next(),
+                };
+                if !choice.as_deref().is_some_and(output::set_color) {
+                    output::option_error("--color", "expects never, auto or always!");
+                    illegal = true;
+                }
+            } else if arg == "--theme" || arg.starts_with("--theme=") {
+                let name = match arg.strip_prefix("--theme=") {
+                    Some(name) => Some(name.to_string()),
+                    None => args.next(),
+                };
+                match name {
+                    Some(name) if !name.is_empty() => theme_arg = Some(name),
+                    _ => {
+                        output::option_error("--theme", "expects a theme name!");
                         illegal = true;
                     }
-                } else if i == 2 {
-                    if arg == "-f" || arg == "--force" {
-                        if force == true {
-                            illegal = double_force();
-                        } else {
-                            force = true;
-                        }
-                    } else if arg == "-m" || arg == "--media" {
-                        if media == true {
-                            illegal = double_media();
-                        } else {
-                            media = true;
-                        }
-                    } else {
-                        if out == "empty" {
-                            out = arg.clone();
-                        } else {
-                            println!("{} {}", "Error:".red().bold(), "bad arguments!".white());
-                            illegal = true;
+                }
+            } else if arg == "--no-force" {
+                no_force = true;
+            } else if arg == "--profile" || arg.starts_with("--profile=") {
+                let name = match arg.strip_prefix("--profile=") {
+                    Some(name) => Some(name.to_string()),
+                    None => args.next(),
+                };
+                match name {
+                    Some(name) if !name.is_empty() => profile_arg = Some(name),
+                    _ => {
+                        output::option_error("--profile", "expects a profile name!");
+                        illegal = true;
+                    }
+                }
+            } else if arg == "--retries" || arg.starts_with("--retries=") {
+                let count = match arg.strip_prefix("--retries=") {
+                    Some(count) => Some(count.to_string()),
+                    None => args.next(),
+                };
+                match count.as_deref().map(str::parse::<u32>) {
+                    Some(Ok(count)) => retries_arg = Some(count),
+                    _ => {
+                        output::option_error("--retries", "expects a number!");
+                        illegal = true;
+                    }
+                }
+            } else if arg == "-H" || arg == "--header" || arg.starts_with("--header=") {
+                let header = match arg.strip_prefix("--header=") {
+                    Some(header) => Some(header.to_string()),
+                    None => args.next(),
+                };
+                match header.as_deref().and_then(parse_header) {
+                    Some(header) => header_args.push(header),
+                    None => {
+                        output::option_error(
+                            "-H/--header",
+                            "expects a header like \"Name: value\"!",
+                        );
+                        illegal = true;
+                    }
+                }
+            } else if arg == "--proxy" || arg.starts_with("--proxy=") {
+                let proxy = match arg.strip_prefix("--proxy=") {
+                    Some(proxy) => Some(proxy.to_string()),
+                    None => args.next(),
+                };
+                match proxy {
+                    Some(proxy) if Url::parse(&proxy).is_ok() => proxy_arg = Some(proxy),
+                    _ => {
+                        output::option_error("--proxy", "expects a URL like http://host:8080!");
+                        illegal = true;
+                    }
+                }
+            } else if arg == "--quality" || arg.starts_with("--quality=") {
+                let quality = match arg.strip_prefix("--quality=") {
+                    Some(quality) => Some(quality.to_string()),
+                    None => args.next(),
+                };
+                match quality {
+                    Some(quality) if quality_args(&quality).is_some() => {
+                        media_options.quality = Some(quality);
+                    }
+                    _ => {
+                        output::option_error(
+                            "--quality",
+                            "expects best, worst, audio or a height like 720!",
+                        );
+                        illegal = true;
+                    }
+                }
+            } else if arg == "--json" {
+                // Already switched on before parsing
+            } else if arg == "--stdout" {
+                file_options.stdout = true;
+            } else if arg == "-O" || arg.starts_with("-O=") {
+                let output = match arg.strip_prefix("-O=") {
+                    Some(output) => Some(output.to_string()),
+                    None => args.next(),
+                };
+                match output {
+                    Some(output) if !output.is_empty() => output_arg = Some(output),
+                    _ => {
+                        output::option_error("-O", "expects an output path or - for stdout!");
+                        illegal = true;
+                    }
+                }
+            } else if arg == "--decompress" {
+                file_options.decompress = true;
+            } else if arg == "--untar" || arg.starts_with("--untar=") {
+                let dir = match arg.strip_prefix("--untar=") {
+                    Some(dir) => Some(dir.to_string()),
+                    None => args.next(),
+                };
+                match dir {
+                    Some(dir) if !dir.is_empty() => file_options.untar = Some(dir),
+                    _ => {
+                        output::option_error("--untar", "expects a directory!");
+                        illegal = true;
+                    }
+                }
+            } else if arg == "--auto-subs" {
+                media_options.auto_subs = true;
+            } else if arg == "--embed-subs" {
+                media_options.embed_subs = true;
+            } else if arg == "--thumbnail" {
+                media_options.thumbnail = true;
+            } else if arg == "--info-json" {
+                media_options.info_json = true;
+            } else if arg == "--deps" || arg.starts_with("--deps=") {
+                let mode = match arg.strip_prefix("--deps=") {
+                    Some(mode) => Some(mode.to_string()),
+                    None => args.next(),
+                };
+                match mode.as_deref().and_then(DepsMode::parse) {
+                    Some(mode) => deps_arg = Some(mode),
+                    None => {
+                        output::option_error("--deps", "expects bundled, system or auto!");
+                        illegal = true;
+                    }
+                }
+            } else if arg == "--" {
+                for extra in args.by_ref() {
+                    if let Some(denied) = denied_ytdlp_arg(&extra) {
+                        illegal = denied_ytdlp_arg_error(denied);
+                    }
+                    media_options.extra_args.push(extra);
+                }
+            } else if arg == "--ytdlp-arg" || arg.starts_with("--ytdlp-arg=") {
+                let extra = match arg.strip_prefix("--ytdlp-arg=") {
+                    Some(extra) => Some(extra.to_string()),
+                    None => args.next(),
+                };
+                match extra {
+                    Some(extra) => {
+                        if let Some(denied) = denied_ytdlp_arg(&extra) {
+                            illegal = denied_ytdlp_arg_error(denied);
                         }
+                        media_options.extra_args.push(extra);
+                    }
+                    None => {
+                        output::option_error("--ytdlp-arg", "expects an argument!");
+                        illegal = true;
+                    }
+                }
+            } else if arg == "-o" || arg == "--output" || arg.starts_with("--output=") {
+                let template = match arg.strip_prefix("--output=") {
+                    Some(template) => Some(template.to_string()),
+                    None => args.next(),
+                };
+                match template {
+                    Some(template) if !template.is_empty() => {
+                        media_options.output_template = Some(template);
+                    }
+                    _ => {
+                        output::option_error("-o/--output", "expects an output template!");
+                        illegal = true;
                     }
-                } else {
-                    println!(
-                        "{} {} {}",
-                        "Error:".red().bold(),
-                        "More than four arguments".white(),
-                        "are not allowed!".red().bold()
-                    );
-                    illegal = true;
                 }
+            } else if arg == "--convert-subs" || arg.starts_with("--convert-subs=") {
+                let format = match arg.strip_prefix("--convert-subs=") {
+                    Some(format) => Some(format.to_string()),
+                    None => args.next(),
+                };
+                match format {
+                    Some(format) if format == "srt" || format == "vtt" => {
+                        media_options.convert_subs = Some(format);
+                    }
+                    _ => {
+                        output::option_error("--convert-subs", "expects srt or vtt!");
+                        illegal = true;
+                    }
+                }
+            } else {
+                positional.push(arg);
             }
+        }
 
-            if url == "empty" {
-                println!("{} {}", "Error".red().bold(), "No URL specified".white());
+        // config.toml fills in whatever the command line leaves open
+        let config = match config::load(profile_arg.as_deref()) {
+            Ok(config) => config,
+            Err(e) => {
+                output::error("config", &e);
+                illegal = true;
+                Config::default()
+            }
+        };
+        if force && no_force {
+            output::error("usage", "-f/--force and --no-force can't be combined!");
+            illegal = true;
+        }
+        force = !no_force && (force || config.force.unwrap_or(false));
+        let deps_mode = deps_arg.or(config.deps).unwrap_or(DepsMode::Auto);
+        let output_dir = config.output_dir();
+        let mut headers = Vec::new();
+        for (name, value) in config.headers {
+            if !valid_header(&name, &value) {
+                output::error("config", &format!("Invalid header {} in config.toml", name));
+                illegal = true;
+            }
+            headers.push((name, value));
+        }
+        // A header given on the command line replaces the one of the same name
+        for (name, value) in header_args {
+            headers.retain(|(set, _)| !set.eq_ignore_ascii_case(&name));
+            headers.push((name, value));
+        }
+        let proxy = proxy_arg.or(config.proxy);
+        if proxy
+            .as_deref()
+            .is_some_and(|proxy| Url::parse(proxy).is_err())
+        {
+            output::error(
+                "config",
+                "proxy in config.toml must be a URL like http://host:8080",
+            );
+            illegal = true;
+        }
+        let config_quality = config.quality;
+        if config_quality
+            .as_deref()
+            .is_some_and(|quality| quality_args(quality).is_none())
+        {
+            output::error(
+                "config",
+                "quality in config.toml must be best, worst, audio or a height like 720",
+            );
+            illegal = true;
+        }
+        let network = Network {
+            retries: retries_arg.or(config.retries),
+            headers,
+            proxy,
+        };
+        file_options.network = network.clone();
+        media_options.network = network;
+
+        if positional.len() > 2 {
+            output::error("usage", "bad arguments!");
+            illegal = true;
+        }
+        let mut positional = positional.into_iter();
+        if let Some(arg) = positional.next() {
+            url = arg;
+        }
+        if let Some(arg) = positional.next() {
+            out = arg;
+        }
+        if let Some(output) = output_arg {
+            if out != "empty" {
+                output::error("usage", "OUTPUT given both as argument and with -O!");
                 illegal = true;
             }
+            out = output;
+        }
+        // - as OUTPUT means stdout, like curl and wget
+        if out == "-" {
+            file_options.stdout = true;
+            out = "empty".to_string();
+        }
 
-            if illegal == false {
-                if media == false {
-                    if let Some(fl_name) = get_file_name_from_url(&url) {
-                        file_name = fl_name;
-                    } else {
-                        println!(
-                            "{} {}",
-                            "Error:".red().bold(),
-                            "No file name found in the URL!".white()
-                        );
-                    }
+        if url == "empty" {
+            output::error("usage", "No URL specified");
+            illegal = true;
+        }
+
+        if !media && !media_options.is_empty() {
+            output::error("usage", "Media options require -m/--media");
+            illegal = true;
+        }
+        if media_options.quality.is_none() {
+            media_options.quality = config_quality;
+        }
+
+        file_options.force = force;
+        if json && (media || file_options.stdout) {
+            output::error(
+                "usage",
+                "--json can't be combined with -m/--media or -O -/--stdout",
+            );
+            illegal = true;
+        } else if media && !file_options.is_empty() {
+            output::error(
+                "usage",
+                "Extraction and decompression options can't be combined with -m/--media",
+            );
+            illegal = true;
+        } else if file_options.stdout
+            && (file_options.extract || file_options.untar.is_some() || out != "empty")
+        {
+            output::option_error(
+                "-O -/--stdout",
+                "takes no OUTPUT and can't be combined with -x/--extract or --untar",
+            );
+            illegal = true;
+        } else if file_options.untar.is_some()
+            && (file_options.extract || file_options.decompress || out != "empty")
+        {
+            output::option_error(
+                "--untar",
+                "takes no OUTPUT and can't be combined with -x/--extract or --decompress",
+            );
+            illegal = true;
+        } else if !file_options.extract && file_options.delete_archive {
+            output::error("usage", "--delete-archive requires -x/--extract");
+            illegal = true;
+        } else if !file_options.extract
+            && file_options.untar.is_none()
+            && file_options.strip_components > 0
+        {
+            output::error(
+                "usage",
+                "--strip-components requires -x/--extract or --untar",
+            );
+            illegal = true;
+        }
+
+        if !illegal {
+            match theme::load(theme_arg.as_deref(), config.theme) {
+                Ok(theme) => output::set_theme(theme),
+                Err(e) => {
+                    output::error("config", &e);
+                    illegal = true;
+                }
+            }
+        }
+
+        if !illegal && (file_options.untar.is_some() || file_options.stdout) {
+            let name = get_file_name_from_url(&url).unwrap_or_else(|| url.clone());
+            download_go(&url, &name, &file_options)?;
+            return Ok(());
+        }
+
+        if !illegal {
+            // The output directory of config.toml stands in for a missing OUTPUT
+            if let Some(dir) = output_dir.filter(|_| out == "empty") {
+                if let Err(e) = fs::create_dir_all(&dir) {
+                    output::error(
+                        "config",
+                        &format!("Failed to create output directory {}: {}", dir, e),
+                    );
+                    return Ok(());
+                }
+                out = dir;
+            }
+            if !media {
+                if let Some(fl_name) = get_file_name_from_url(&url) {
+                    file_name = fl_name;
                 } else {
-                    ytdlp_check(update)?;
+                    output::error("usage", "No file name found in the URL!");
+                    return Ok(());
                 }
-                if let Some(home) = home_dir() {
-                    if out.starts_with('~') && system == "win" {
-                        out = remove_tilde(&out);
-                        if out.starts_with('\\') {
-                            out = remove_backslash_start(&out);
-                            out = home.join(out.clone()).to_string_lossy().to_string();
-                        } else if out.starts_with('/') {
-                            out = remove_slash_start(&out);
-                            out = home.join(out.clone()).to_string_lossy().to_string();
-                        } else {
-                            println!(
-                                "{} {}",
-                                "Error".red().bold(),
-                                "Home directory written incorrectly!".white()
-                            );
-                            return Ok(());
-                        }
+            }
+            if let Some(home) = home_dir() {
+                if out.starts_with('~') && system == "win" {
+                    out = remove_tilde(&out);
+                    if out.starts_with('\\') {
+                        out = remove_backslash_start(&out);
+                        out = home.join(out.clone()).to_string_lossy().to_string();
+                    } else if out.starts_with('/') {
+                        out = remove_slash_start(&out);
+                        out = home.join(out.clone()).to_string_lossy().to_string();
+                    } else {
+                        output::error("usage", "Home directory written incorrectly!");
+                        return Ok(());
                     }
-                    if Path::new(&out).exists() {
-                        if !Path::new(&out).is_dir() {
-                            if media == true {
-                                println!(
-                                    "{} {} {} {}",
+                }
+                if Path::new(&out).exists() {
+                    if !Path::new(&out).is_dir() {
+                        if media {
+                            ytdlp_file_go(deps_mode, url, out, force, media_options)?;
+                        } else if Path::new(&out).is_file() {
+                            if force {
+                                let mes = format!(
+                                    "{} {}",
                                     "Error:".red().bold(),
-                                    "You can't use".white(),
-                                    "filename,".white().bold(),
-                                    "when downloading media.".white()
+                                    "Failed to remove file!".white()
                                 );
-                                return Ok(());
+                                fs::remove_file(out.clone()).expect(&mes);
+                                file_go(url, out, &file_options)?;
                             } else {
-                                if Path::new(&out).is_file() {
-                                    if force == true {
-                                        let mes = format!(
-                                            "{} {}",
-                                            "Error:".red().bold(),
-                                            "Failed to remove file!".white()
-                                        );
-                                        fs::remove_file(out.clone()).expect(&mes);
-                                        go(url, out)?;
-                                    } else {
-                                        println!(
-                                            "{} {}",
-                                            "Error:".red().bold(),
-                                            "File already exists!".white()
-                                        );
-                                    }
-                                }
-                            }
-                        } else if Path::new(&out).is_dir() {
-                            if out.ends_with('/') {
-                                out = remove_slash(&out);
-                            }
-                            if out.ends_with('\\') && system == "win" {
-                                out = remove_backslash(&out);
+                                output::error("exists", "File already exists!");
                             }
+                        }
+                    } else if Path::new(&out).is_dir() {
+                        if out.ends_with('/') {
+                            out = remove_slash(&out);
+                        }
+                        if out.ends_with('\\') && system == "win" {
+                            out = remove_backslash(&out);
+                        }
 
-                            if media == true {
-                                ytdlp_go(system, url, out, force)?;
-                            } else {
-                                out = Path::new(&out)
-                                    .join(file_name)
-                                    .to_string_lossy()
-                                    .to_string();
+                        if media {
+                            ytdlp_go(deps_mode, url, out, force, &media_options)?;
+                        } else {
+                            out = Path::new(&out)
+                                .join(file_name)
+                                .to_string_lossy()
+                                .to_string();
 
-                                file_check_go(url, out, force)?;
-                            }
+                            file_check_go(url, out, force, &file_options)?;
                         }
-                    } else {
-                        if media == false {
-                            let out_bare = get_dir_from_path(&out);
-                            if Path::new(&out_bare).exists() && Path::new(&out_bare).is_dir() {
-                                if out_bare == "." {
-                                    file_check_go(url, out, force)?;
-                                } else {
-                                    go(url, out)?;
-                                }
-                            } else if out_bare == "" {
-                                if out == "empty" {
-                                    out = file_name;
-                                }
-                                go(url, out)?;
-                            } else if out == "empty" {
-                                out = file_name;
-                                file_check_go(url, out, force)?;
-                            } else {
-                                println!(
-                                    "{} {}",
-                                    "Error".red().bold(),
-                                    "Output directory couldn't be found!".white()
-                                );
-                            }
+                    }
+                } else if !media {
+                    let out_bare = get_dir_from_path(&out);
+                    if Path::new(&out_bare).exists() && Path::new(&out_bare).is_dir() {
+                        if out_bare == "." {
+                            file_check_go(url, out, force, &file_options)?;
                         } else {
-                            if out == "empty" {
-                                out = ".".to_string();
-                                ytdlp_go(system, url, out, force)?;
-                            } else {
-                                println!(
-                                    "{} {}",
-                                    "Error".red().bold(),
-                                    "Output directory couldn't be found!".white()
-                                );
-                            }
+                            file_go(url, out, &file_options)?;
+                        }
+                    } else if out_bare.is_empty() {
+                        if out == "empty" {
+                            out = file_name;
                         }
+                        file_go(url, out, &file_options)?;
+                    } else if out == "empty" {
+                        out = file_name;
+                        file_check_go(url, out, force, &file_options)?;
+                    } else {
+                        output::error("not_found", "Output directory couldn't be found!");
                     }
+                } else if out == "empty" {
+                    out = ".".to_string();
+                    ytdlp_go(deps_mode, url, out, force, &media_options)?;
+                } else if media_options.output_template.is_some() {
+                    // With an explicit template the output is always a base directory
+                    fs::create_dir_all(&out)?;
+                    ytdlp_go(deps_mode, url, out, force, &media_options)?;
                 } else {
-                    println!(
-                        "{} {}",
-                        "Error:".red().bold(),
-                        "Unable to determine home directory!".white()
-                    );
+                    ytdlp_file_go(deps_mode, url, out, force, media_options)?;
                 }
+            } else {
+                output::error("not_found", "Unable to determine home directory!");
             }
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve;
+    use flate2::write::GzEncoder;
+    use tempfile::tempdir;
+
+    #[test]
+    fn sub_langs_need_known_codes() {
+        assert!(looks_like_sub_langs("en"));
+        assert!(looks_like_sub_langs("en,de"));
+        assert!(looks_like_sub_langs("pt-BR,en.*"));
+        assert!(looks_like_sub_langs("all"));
+        assert!(!looks_like_sub_langs("vid"));
+        assert!(!looks_like_sub_langs("xx"));
+        assert!(!looks_like_sub_langs("en,vid"));
+        assert!(!looks_like_sub_langs("https://example.com"));
+    }
+
+    #[test]
+    fn error_status_is_not_saved() {
+        let base = serve(vec![("/f404.bin", 404, b"<html>not here</html>".to_vec())]);
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("f404.bin");
+        let e = download_file(
+            &format!("{}/f404.bin", base),
+            &path.to_string_lossy(),
+            &FileOptions::default(),
+        )
+        .unwrap_err();
+        assert_eq!(error_code(e.as_ref()), "http");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn retries_running_out_fail() {
+        let base = serve(vec![(
+            "/f503.bin",
+            503,
+            b"<html>error page</html>".to_vec(),
+        )]);
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("f503.bin");
+        let file_options = FileOptions {
+            network: Network {
+                retries: Some(1),
+                ..Network::default()
+            },
+            ..FileOptions::default()
+        };
+        let e = download_file(
+            &format!("{}/f503.bin", base),
+            &path.to_string_lossy(),
+            &file_options,
+        )
+        .unwrap_err();
+        assert_eq!(error_code(e.as_ref()), "http");
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn gzip_body_is_saved_plain() {
+        let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"plain text").unwrap();
+        let base = serve(vec![("/data.txt.gz", 200, encoder.finish().unwrap())]);
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("data.txt.gz");
+        let file_options = FileOptions {
+            decompress: true,
+            ..FileOptions::default()
+        };
+        let url = format!("{}/data.txt.gz", base);
+        let saved = download_file(&url, &path.to_string_lossy(), &file_options).unwrap();
+        assert_eq!(PathBuf::from(&saved), dir.path().join("data.txt"));
+        assert_eq!(fs::read(&saved).unwrap(), b"plain text");
+        assert!(!path.exists());
+
+        // The stripped name is checked like any other existing file
+        let e = download_file(&url, &path.to_string_lossy(), &file_options).unwrap_err();
+        assert_eq!(error_code(e.as_ref()), "exists");
+    }
+
+    #[test]
+    fn untar_unpacks_from_the_network() {
+        let mut builder =
+            tar::Builder::new(GzEncoder::new(Vec::new(), flate2::Compression::default()));
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(4);
+        builder
+            .append_data(&mut header, "pkg-1.0/bin/tool", &b"tool"[..])
+            .unwrap();
+        let body = builder.into_inner().unwrap().finish().unwrap();
+        let base = serve(vec![("/pkg.tgz", 200, body)]);
+        let dir = tempdir().unwrap();
+        let out = dir.path().join("out");
+        let file_options = FileOptions {
+            untar: Some(out.to_string_lossy().to_string()),
+            strip_components: 1,
+            ..FileOptions::default()
+        };
+        download_file(&format!("{}/pkg.tgz", base), "pkg.tgz", &file_options).unwrap();
+        assert_eq!(fs::read(out.join("bin/tool")).unwrap(), b"tool");
+        assert!(!out.join("pkg-1.0").exists());
+        assert!(!Path::new("pkg.tgz").exists());
+    }
+
+    #[test]
+    fn url_ending_in_a_slash_streams() {
+        let base = serve(vec![("/", 200, b"index\n".to_vec())]);
+        let url = format!("{}/", base);
+        assert_eq!(get_file_name_from_url(&url), None);
+        // The name main falls back to for -O - and --untar
+        let name = get_file_name_from_url(&url).unwrap_or_else(|| url.clone());
+        let file_options = FileOptions {
+            stdout: true,
+            ..FileOptions::default()
+        };
+        assert_eq!(download_file(&url, &name, &file_options).unwrap(), "-");
+
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_size(4);
+        builder
+            .append_data(&mut header, "file", &b"data"[..])
+            .unwrap();
+        let base = serve(vec![("/", 200, builder.into_inner().unwrap())]);
+        let url = format!("{}/", base);
+        let dir = tempdir().unwrap();
+        let file_options = FileOptions {
+            untar: Some(dir.path().to_string_lossy().to_string()),
+            ..FileOptions::default()
+        };
+        download_file(&url, &url, &file_options).unwrap();
+        assert_eq!(fs::read(dir.path().join("file")).unwrap(), b"data");
+    }
+
+    #[test]
+    fn output_options_stay_with_pls() {
+        assert_eq!(denied_ytdlp_arg("-o"), Some("-o"));
+        assert_eq!(denied_ytdlp_arg("-ox.mp4"), Some("-o"));
+        assert_eq!(denied_ytdlp_arg("--output=x"), Some("--output"));
+        assert_eq!(denied_ytdlp_arg("-P"), Some("-P"));
+        assert_eq!(denied_ytdlp_arg("--format"), None);
+    }
+
+    #[test]
+    fn file_extension_is_kept() {
+        assert_eq!(format_args("mp4"), ["--remux-video", "mp4"]);
+        assert_eq!(format_args("mp3"), ["-x", "--audio-format", "mp3"]);
+        assert!(format_args("txt").is_empty());
+    }
+}
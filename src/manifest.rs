@@ -0,0 +1,405 @@
+use crate::deps::find_in_path;
+use crate::paths::config_dir;
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+
+// Where yt-dlp and ffmpeg come from by default. {release} is replaced by "latest/download",
+// or by "download/<tag>" when yt-dlp is pinned. Termux builds are listed under android.
+// Entries without libc fit both glibc and musl, arch "any" entries are the last resort and
+// requires names a program that has to be in PATH for the entry to be used.
+const DEFAULT_MANIFEST: &str = r#"
+[[artifact]]
+tool = "yt-dlp"
+os = "linux"
+arch = "x86_64"
+libc = "gnu"
+url = "https://github.com/yt-dlp/yt-dlp/releases/{release}/yt-dlp_linux"
+checksums = "https://github.com/yt-dlp/yt-dlp/releases/{release}/SHA2-256SUMS"
+
+[[artifact]]
+tool = "yt-dlp"
+os = "linux"
+arch = "aarch64"
+libc = "gnu"
+url = "https://github.com/yt-dlp/yt-dlp/releases/{release}/yt-dlp_linux_aarch64"
+checksums = "https://github.com/yt-dlp/yt-dlp/releases/{release}/SHA2-256SUMS"
+
+[[artifact]]
+tool = "yt-dlp"
+os = "linux"
+arch = "arm"
+libc = "gnu"
+url = "https://github.com/yt-dlp/yt-dlp/releases/{release}/yt-dlp_linux_armv7l"
+checksums = "https://github.com/yt-dlp/yt-dlp/releases/{release}/SHA2-256SUMS"
+
+[[artifact]]
+tool = "yt-dlp"
+os = "linux"
+arch = "x86_64"
+libc = "musl"
+url = "https://github.com/yt-dlp/yt-dlp/releases/{release}/yt-dlp_musllinux"
+checksums = "https://github.com/yt-dlp/yt-dlp/releases/{release}/SHA2-256SUMS"
+
+[[artifact]]
+tool = "yt-dlp"
+os = "linux"
+arch = "aarch64"
+libc = "musl"
+url = "https://github.com/yt-dlp/yt-dlp/releases/{release}/yt-dlp_musllinux_aarch64"
+checksums = "https://github.com/yt-dlp/yt-dlp/releases/{release}/SHA2-256SUMS"
+
+[[artifact]]
+tool = "yt-dlp"
+os = "linux"
+arch = "any"
+requires = "python3"
+url = "https://github.com/yt-dlp/yt-dlp/releases/{release}/yt-dlp"
+checksums = "https://github.com/yt-dlp/yt-dlp/releases/{release}/SHA2-256SUMS"
+
+[[artifact]]
+tool = "yt-dlp"
+os = "macos"
+arch = "any"
+url = "https://github.com/yt-dlp/yt-dlp/releases/{release}/yt-dlp_macos"
+checksums = "https://github.com/yt-dlp/yt-dlp/releases/{release}/SHA2-256SUMS"
+
+[[artifact]]
+tool = "yt-dlp"
+os = "android"
+arch = "x86_64"
+url = "https://storage.googleapis.com/mochov-public/pls/amd64/yt-dlp-amd64.tar.xz"
+
+[[artifact]]
+tool = "yt-dlp"
+os = "android"
+arch = "aarch64"
+url = "https://storage.googleapis.com/mochov-public/pls/aarch64/yt-dlp-aarch64.tar.xz"
+
+[[artifact]]
+tool = "yt-dlp"
+os = "windows"
+arch = "x86_64"
+url = "https://github.com/yt-dlp/yt-dlp/releases/{release}/yt-dlp.exe"
+checksums = "https://github.com/yt-dlp/yt-dlp/releases/{release}/SHA2-256SUMS"
+
+[[artifact]]
+tool = "yt-dlp"
+os = "windows"
+arch = "x86"
+url = "https://github.com/yt-dlp/yt-dlp/releases/{release}/yt-dlp_x86.exe"
+checksums = "https://github.com/yt-dlp/yt-dlp/releases/{release}/SHA2-256SUMS"
+
+[[artifact]]
+tool = "yt-dlp"
+os = "windows"
+arch = "aarch64"
+url = "https://github.com/yt-dlp/yt-dlp/releases/{release}/yt-dlp_arm64.exe"
+checksums = "https://github.com/yt-dlp/yt-dlp/releases/{release}/SHA2-256SUMS"
+
+[[artifact]]
+tool = "ffmpeg"
+os = "linux"
+arch = "x86_64"
+url = "https://github.com/BtbN/FFmpeg-Builds/releases/download/latest/ffmpeg-master-latest-linux64-gpl.tar.xz"
+checksums = "https://github.com/BtbN/FFmpeg-Builds/releases/download/latest/checksums.sha256"
+
+[[artifact]]
+tool = "ffmpeg"
+os = "linux"
+arch = "aarch64"
+url = "https://github.com/BtbN/FFmpeg-Builds/releases/download/latest/ffmpeg-master-latest-linuxarm64-gpl.tar.xz"
+checksums = "https://github.com/BtbN/FFmpeg-Builds/releases/download/latest/checksums.sha256"
+
+[[artifact]]
+tool = "ffmpeg"
+os = "android"
+arch = "x86_64"
+url = "https://storage.googleapis.com/mochov-public/pls/amd64/ffmpeg-master-latest-linux64-gpl.tar.xz"
+
+[[artifact]]
+tool = "ffmpeg"
+os = "android"
+arch = "aarch64"
+url = "https://storage.googleapis.com/mochov-public/pls/aarch64/ffmpeg-master-latest-linuxarm64-gpl.tar.xz"
+
+[[artifact]]
+tool = "ffmpeg"
+os = "windows"
+arch = "x86_64"
+url = "https://www.gyan.dev/ffmpeg/builds/ffmpeg-release-essentials.zip"
+checksums = "https://www.gyan.dev/ffmpeg/builds/ffmpeg-release-essentials.zip.sha256"
+"#;
+
+// The machine bundled tools are picked for, arch uses the names of std::env::consts::ARCH
+pub struct Platform {
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub libc: Option<&'static str>,
+}
+
+impl Platform {
+    pub fn current(termux: bool) -> Platform {
+        let os = if termux {
+            "android"
+        } else {
+            std::env::consts::OS
+        };
+        let libc = (os == "linux").then(|| if is_musl() { "musl" } else { "gnu" });
+        Platform {
+            os,
+            arch: std::env::consts::ARCH,
+            libc,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self.libc {
+            Some(libc) => format!("{} {} ({})", self.os, self.arch, libc),
+            None => format!("{} {}", self.os, self.arch),
+        }
+    }
+}
+
+// musl systems (Alpine, Void musl) ship their dynamic loader as /lib/ld-musl-<arch>.so.1
+fn is_musl() -> bool {
+    fs::read_dir("/lib").is_ok_and(|entries| {
+        entries
+            .flatten()
+            .any(|entry| entry.file_name().to_string_lossy().starts_with("ld-musl-"))
+    })
+}
+
+#[derive(Deserialize, Default)]
+struct Manifest {
+    base: Option<String>,
+    #[serde(default, rename = "artifact")]
+    artifacts: Vec<Artifact>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Artifact {
+    tool: String,
+    os: String,
+    arch: String,
+    libc: Option<String>,
+    requires: Option<String>,
+    pub url: String,
+    pub checksums: Option<String>,
+}
+
+// Artifacts from the user manifest win over the built-in ones, even "any" over an exact arch
+pub struct Sources {
+    user: Vec<Artifact>,
+    builtin: Vec<Artifact>,
+}
+
+impl Artifact {
+    // Only {release} URLs can fetch a pinned tag, mirrored built-in entries hold one build
+    pub fn pinnable(&self) -> bool {
+        self.url.contains("{release}")
+    }
+
+    fn fits(&self, platform: &Platform) -> bool {
+        self.os == platform.os
+            && self
+                .libc
+                .as_deref()
+                .is_none_or(|libc| Some(libc) == platform.libc)
+            && self
+                .requires
+                .as_deref()
+                .is_none_or(|program| find_in_path(program).is_some())
+    }
+}
+
+impl Sources {
+    // Exact arch matches win over "any" entries, there is no guessing beyond that
+    pub fn find(&self, tool: &str, platform: &Platform) -> Option<&Artifact> {
+        find_in(&self.user, tool, platform).or_else(|| find_in(&self.builtin, tool, platform))
+    }
+}
+
+fn find_in<'a>(artifacts: &'a [Artifact], tool: &str, platform: &Platform) -> Option<&'a Artifact> {
+    let fitting = || {
+        artifacts
+            .iter()
+            .filter(move |artifact| artifact.tool == tool && artifact.fits(platform))
+    };
+    fitting()
+        .find(|artifact| artifact.arch == platform.arch)
+        .or_else(|| fitting().find(|artifact| artifact.arch == "any"))
+}
+
+pub fn manifest_path() -> Option<PathBuf> {
+    std::env::var_os("PLS_DEPS_MANIFEST")
+        .map(PathBuf::from)
+        .or_else(|| config_dir().map(|config| config.join("deps.toml")))
+}
+
+// Relative entries are resolved against the mirror base, which may be a URL or a local directory
+fn resolve(base: Option<&str>, location: &str) -> String {
+    match base {
+        Some(base) if !location.contains("://") && !PathBuf::from(location).is_absolute() => {
+            format!("{}/{}", base.trim_end_matches(['/', '\\']), location)
+        }
+        _ => location.to_string(),
+    }
+}
+
+fn file_name(location: &str) -> &str {
+    location.rsplit(['/', '\\']).next().unwrap_or(location)
+}
+
+pub fn load() -> Result<Sources, String> {
+    let mut user = Manifest::default();
+    if let Some(path) = manifest_path().filter(|path| path.is_file()) {
+        let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        user = toml::from_str(&text)
+            .map_err(|e| format!("{}: {}", path.to_string_lossy(), e.message()))?;
+    }
+    let defaults: Manifest = toml::from_str(DEFAULT_MANIFEST).map_err(|e| e.to_string())?;
+
+    // PLS_MIRROR wins over the manifest
+    let base = std::env::var("PLS_MIRROR").ok().or(user.base.take());
+    Ok(combine(user, defaults, base.as_deref()))
+}
+
+// With a base set the built-in artifacts are looked up in the mirror by their file names
+fn combine(user: Manifest, defaults: Manifest, base: Option<&str>) -> Sources {
+    let mut user_artifacts = Vec::new();
+    for mut artifact in user.artifacts {
+        artifact.url = resolve(base, &artifact.url);
+        artifact.checksums = artifact
+            .checksums
+            .map(|checksums| resolve(base, &checksums));
+        user_artifacts.push(artifact);
+    }
+    let mut builtin = Vec::new();
+    for mut artifact in defaults.artifacts {
+        if base.is_some() {
+            artifact.url = resolve(base, file_name(&artifact.url));
+            artifact.checksums = artifact
+                .checksums
+                .map(|checksums| resolve(base, file_name(&checksums)));
+        }
+        builtin.push(artifact);
+    }
+    Sources {
+        user: user_artifacts,
+        builtin,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_entries_follow_the_base() {
+        assert_eq!(resolve(None, "yt-dlp_linux"), "yt-dlp_linux");
+        assert_eq!(
+            resolve(Some("https://mirror.example/pls/"), "tools/yt-dlp_linux"),
+            "https://mirror.example/pls/tools/yt-dlp_linux"
+        );
+        assert_eq!(
+            resolve(Some("/srv/mirror"), "ffmpeg.tar.xz"),
+            "/srv/mirror/ffmpeg.tar.xz"
+        );
+        assert_eq!(
+            resolve(Some("/srv/mirror"), "https://example.com/yt-dlp"),
+            "https://example.com/yt-dlp"
+        );
+        assert_eq!(resolve(Some("/srv/mirror"), "/opt/yt-dlp"), "/opt/yt-dlp");
+    }
+
+    #[test]
+    fn mirrors_serve_built_in_entries_by_file_name() {
+        let user: Manifest = toml::from_str(
+            r#"
+            [[artifact]]
+            tool = "yt-dlp"
+            os = "linux"
+            arch = "x86_64"
+            url = "builds/yt-dlp"
+            checksums = "https://example.com/SUMS"
+            "#,
+        )
+        .unwrap();
+        let defaults: Manifest = toml::from_str(DEFAULT_MANIFEST).unwrap();
+        let sources = combine(user, defaults, Some("/srv/mirror"));
+        assert_eq!(sources.user[0].url, "/srv/mirror/builds/yt-dlp");
+        assert_eq!(
+            sources.user[0].checksums.as_deref(),
+            Some("https://example.com/SUMS")
+        );
+        assert_eq!(sources.builtin[0].url, "/srv/mirror/yt-dlp_linux");
+        assert_eq!(
+            sources.builtin[0].checksums.as_deref(),
+            Some("/srv/mirror/SHA2-256SUMS")
+        );
+        assert!(!sources.builtin[0].pinnable());
+
+        let defaults: Manifest = toml::from_str(DEFAULT_MANIFEST).unwrap();
+        let sources = combine(Manifest::default(), defaults, None);
+        assert!(sources.builtin[0].pinnable());
+    }
+
+    fn artifact(arch: &str, libc: Option<&str>, url: &str) -> Artifact {
+        Artifact {
+            tool: "yt-dlp".to_string(),
+            os: "linux".to_string(),
+            arch: arch.to_string(),
+            libc: libc.map(str::to_string),
+            requires: None,
+            url: url.to_string(),
+            checksums: None,
+        }
+    }
+
+    #[test]
+    fn user_entries_win_before_arch_matters() {
+        let platform = Platform {
+            os: "linux",
+            arch: "x86_64",
+            libc: Some("musl"),
+        };
+        let found = |sources: &Sources| sources.find("yt-dlp", &platform).unwrap().url.clone();
+        let builtin = vec![
+            artifact("x86_64", Some("gnu"), "builtin-gnu"),
+            artifact("x86_64", Some("musl"), "builtin-musl"),
+            artifact("any", None, "builtin-any"),
+        ];
+
+        let sources = Sources {
+            user: Vec::new(),
+            builtin: builtin.clone(),
+        };
+        assert_eq!(found(&sources), "builtin-musl");
+
+        // A user entry for "any" arch beats the exact built-in one
+        let sources = Sources {
+            user: vec![artifact("any", None, "user-any")],
+            builtin: builtin.clone(),
+        };
+        assert_eq!(found(&sources), "user-any");
+
+        // Within one manifest the exact arch still wins, and the wrong libc never fits
+        let sources = Sources {
+            user: vec![
+                artifact("any", None, "user-any"),
+                artifact("x86_64", Some("gnu"), "user-gnu"),
+                artifact("x86_64", None, "user-x86_64"),
+            ],
+            builtin: builtin.clone(),
+        };
+        assert_eq!(found(&sources), "user-x86_64");
+
+        let sources = Sources {
+            user: vec![artifact("aarch64", None, "user-aarch64")],
+            builtin,
+        };
+        assert_eq!(found(&sources), "builtin-musl");
+        assert!(sources.find("ffmpeg", &platform).is_none());
+    }
+}
@@ -0,0 +1,222 @@
+use crate::theme::{self, Theme};
+use colored::*;
+use indicatif::ProgressBar;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::io::{self, IsTerminal, Read, Write};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+// --json swaps every message for newline-delimited JSON events on stdout
+static JSON: AtomicBool = AtomicBool::new(false);
+
+// With -O - stdout carries the downloaded data, so messages must stay on stderr
+static STDOUT_DATA: AtomicBool = AtomicBool::new(false);
+
+// 0 is -q, 1 the default, 2 and 3 are -v and -vv
+static VERBOSITY: AtomicU8 = AtomicU8::new(1);
+
+static THEME: OnceLock<Theme> = OnceLock::new();
+static PLAIN: OnceLock<Theme> = OnceLock::new();
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+
+pub fn set_json() {
+    JSON.store(true, Ordering::Relaxed);
+}
+
+pub fn json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+pub fn set_stdout_data() {
+    STDOUT_DATA.store(true, Ordering::Relaxed);
+}
+
+pub fn set_verbosity(level: u8) {
+    VERBOSITY.store(level, Ordering::Relaxed);
+}
+
+// Progress bars and status messages are left out with -q, and with --json which has its own
+pub fn quiet() -> bool {
+    json() || VERBOSITY.load(Ordering::Relaxed) == 0
+}
+
+// The boxes and glyphs only make sense on a terminal, logs get plain lines
+pub fn fancy() -> bool {
+    io::stdout().is_terminal()
+}
+
+pub fn set_theme(theme: Theme) {
+    let _ = THEME.set(theme);
+}
+
+// The configured theme on terminals, plain lines everywhere else
+pub fn theme() -> &'static Theme {
+    if fancy() {
+        THEME.get_or_init(Theme::default)
+    } else {
+        PLAIN.get_or_init(theme::plain)
+    }
+}
+
+// Details for -v (level 1) and -vv (level 2), on stderr so they never mix with downloaded data
+pub fn verbose(level: u8, message: &str) {
+    if VERBOSITY.load(Ordering::Relaxed) > level {
+        eprintln!("{}", message.dimmed());
+    }
+}
+
+fn set_colors(enabled: bool) {
+    colored::control::set_override(enabled);
+    console::set_colors_enabled(enabled);
+    console::set_colors_enabled_stderr(enabled);
+}
+
+// colored already honours NO_COLOR, the progress bar styles come from console which doesn't
+pub fn init_color() {
+    if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+        set_colors(false);
+    }
+}
+
+// --color never|auto|always, auto leaves it to the terminal and NO_COLOR
+pub fn set_color(choice: &str) -> bool {
+    match choice {
+        "always" => set_colors(true),
+        "never" => set_colors(false),
+        "auto" => {}
+        _ => return false,
+    }
+    true
+}
+
+fn event(event: Value) {
+    let mut stdout = io::stdout().lock();
+    let _ = writeln!(stdout, "{}", event);
+    let _ = stdout.flush();
+}
+
+// Codes are stable for scripts: usage, config, exists, not_found, network, http, io, extract,
+// download
+pub fn error(code: &str, message: &str) {
+    if json() {
+        event(json!({ "event": "error", "code": code, "message": message }));
+    } else {
+        print_error(format!("{} {}", "Error:".red().bold(), message.white()));
+    }
+}
+
+fn print_error(line: String) {
+    if STDOUT_DATA.load(Ordering::Relaxed) {
+        eprintln!("{}", line);
+    } else {
+        println!("{}", line);
+    }
+}
+
+// Errors about a command line option, the option is highlighted
+pub fn option_error(option: &str, message: &str) {
+    if json() {
+        error("usage", &format!("{} {}", option, message));
+    } else {
+        print_error(format!(
+            "{} {} {}",
+            "Error:".red().bold(),
+            option.white().bold(),
+            message.white()
+        ));
+    }
+}
+
+pub fn start(url: &str, file: &str, path: &str, size: Option<u64>, status: u16) {
+    if json() {
+        event(json!({
+            "event": "start",
+            "url": url,
+            "status": status,
+            "file": file,
+            "path": path,
+            "size": size,
+        }));
+    }
+}
+
+pub fn extracted(archive: &str, dir: &str) {
+    if json() {
+        event(json!({ "event": "extract", "archive": archive, "path": dir }));
+    }
+}
+
+// Hashes what a download hands on and reports progress every half second in JSON mode.
+// Progress counts the bytes off the network, which stay compressed with --decompress.
+pub struct Tracker<R> {
+    inner: R,
+    pb: ProgressBar,
+    total: Option<u64>,
+    hasher: Sha256,
+    // Bytes handed on to the file, the bar counts them before decompression
+    written: u64,
+    started: Instant,
+    last: Instant,
+    last_bytes: u64,
+}
+
+impl<R: Read> Tracker<R> {
+    pub fn new(inner: R, pb: ProgressBar, total: Option<u64>) -> Tracker<R> {
+        let now = Instant::now();
+        Tracker {
+            inner,
+            pb,
+            total,
+            hasher: Sha256::new(),
+            written: 0,
+            started: now,
+            last: now,
+            last_bytes: 0,
+        }
+    }
+
+    // The size and sha256 are taken over the written data, so they match the saved file
+    pub fn finish(self, path: &str) {
+        if !json() {
+            return;
+        }
+        let sha256: String = self
+            .hasher
+            .finalize()
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        event(json!({
+            "event": "finish",
+            "path": path,
+            "bytes": self.written,
+            "duration": self.started.elapsed().as_secs_f64(),
+            "sha256": sha256,
+        }));
+    }
+}
+
+impl<R: Read> Read for Tracker<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.written += read as u64;
+        let elapsed = self.last.elapsed();
+        if json() && elapsed >= PROGRESS_INTERVAL {
+            let bytes = self.pb.position();
+            let speed = (bytes - self.last_bytes) as f64 / elapsed.as_secs_f64();
+            event(json!({
+                "event": "progress",
+                "bytes": bytes,
+                "total": self.total,
+                "speed": speed.round() as u64,
+            }));
+            self.last = Instant::now();
+            self.last_bytes = bytes;
+        }
+        Ok(read)
+    }
+}
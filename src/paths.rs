@@ -0,0 +1,52 @@
+use dirs::home_dir;
+use std::path::PathBuf;
+
+// PLS_HOME keeps everything pls writes under one directory, handy for tests and sandboxes
+fn pls_home(kind: &str) -> Option<PathBuf> {
+    std::env::var_os("PLS_HOME")
+        .filter(|home| !home.is_empty())
+        .map(|home| PathBuf::from(home).join(kind))
+}
+
+// XDG variables only count when they hold an absolute path, as the spec asks
+#[cfg(unix)]
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| home_dir().map(|home| home.join(fallback)))
+        .map(|dir| dir.join("pls"))
+}
+
+// Installed yt-dlp and ffmpeg, receipts and pins
+#[cfg(unix)]
+pub fn data_dir() -> Option<PathBuf> {
+    pls_home("data").or_else(|| xdg_dir("XDG_DATA_HOME", ".local/share"))
+}
+
+#[cfg(windows)]
+pub fn data_dir() -> Option<PathBuf> {
+    pls_home("data").or_else(|| home_dir().map(|home| home.join("AppData\\Roaming\\pls")))
+}
+
+// Downloaded archives while they are being verified and unpacked
+#[cfg(unix)]
+pub fn cache_dir() -> Option<PathBuf> {
+    pls_home("cache").or_else(|| xdg_dir("XDG_CACHE_HOME", ".cache"))
+}
+
+#[cfg(windows)]
+pub fn cache_dir() -> Option<PathBuf> {
+    pls_home("cache").or_else(|| dirs::cache_dir().map(|cache| cache.join("pls")))
+}
+
+// Configuration files such as deps.toml
+#[cfg(unix)]
+pub fn config_dir() -> Option<PathBuf> {
+    pls_home("config").or_else(|| xdg_dir("XDG_CONFIG_HOME", ".config"))
+}
+
+#[cfg(windows)]
+pub fn config_dir() -> Option<PathBuf> {
+    pls_home("config").or_else(|| dirs::config_dir().map(|config| config.join("pls")))
+}
@@ -0,0 +1,421 @@
+use crate::theme::{Layout, Theme};
+use indicatif::{
+    style::ProgressTracker, HumanBytes, HumanDuration, ProgressBar, ProgressDrawTarget,
+    ProgressState, ProgressStyle,
+};
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use terminal_size::{terminal_size, terminal_size_of, Width};
+
+// The bar is drawn on stderr, stdout may well be a pipe with -O -
+fn term_width() -> u16 {
+    match terminal_size_of(io::stderr()).or_else(terminal_size) {
+        Some((Width(w), _)) => w,
+        None => 80, // fallback default
+    }
+}
+
+// Shortens text to max characters by cutting out its middle, both ends of a URL say the most
+fn fit(text: &str, max: usize, ellipsis: &str) -> String {
+    let count = text.chars().count();
+    if count <= max {
+        return text.to_string();
+    }
+    // Too narrow for the ellipsis and any text, what fits of the ellipsis stands in for it
+    if max <= ellipsis.chars().count() {
+        return ellipsis.chars().take(max).collect();
+    }
+    let keep = max - ellipsis.chars().count();
+    let head = keep.div_ceil(2);
+    let tail = keep - head;
+    let start: String = text.chars().take(head).collect();
+    let end: String = text.chars().skip(count - tail).collect();
+    format!("{}{}{}", start, ellipsis, end)
+}
+
+// Highest smoothed speed so far, shared by the {peak_speed} key and the final summary.
+// The first second is left out, the estimate jumps around until it has some samples.
+#[derive(Clone, Default)]
+struct Peak(Arc<AtomicU64>);
+
+impl Peak {
+    fn get(&self) -> u64 {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl ProgressTracker for Peak {
+    fn clone_box(&self) -> Box<dyn ProgressTracker> {
+        Box::new(self.clone())
+    }
+
+    fn tick(&mut self, state: &ProgressState, _: Instant) {
+        if state.elapsed() >= Duration::from_secs(1) {
+            self.0.fetch_max(state.per_sec() as u64, Ordering::Relaxed);
+        }
+    }
+
+    fn reset(&mut self, _: &ProgressState, _: Instant) {
+        self.0.store(0, Ordering::Relaxed);
+    }
+
+    fn write(&self, _: &ProgressState, w: &mut dyn fmt::Write) {
+        let _ = write!(w, "{}/s", HumanBytes(self.get()));
+    }
+}
+
+fn average(bytes: u64, elapsed: Duration) -> u64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        (bytes as f64 / secs) as u64
+    } else {
+        0
+    }
+}
+
+// Everything needed to lay the display out again for another terminal width
+#[derive(Clone)]
+struct Display {
+    theme: &'static Theme,
+    url: String,
+    file_name: String,
+    known: bool,
+    peak: Peak,
+}
+
+// Template, header and the lines "Download complete!" goes under, for one width
+struct Look {
+    template: String,
+    message: String,
+    finish: String,
+}
+
+impl Display {
+    // Border and arrow in front of a line, the minimal layout has neither
+    fn lead(&self, side: &str, arrow: bool) -> String {
+        if arrow {
+            format!("{}{} ", side, self.theme.accent(&self.theme.arrow))
+        } else {
+            side.to_string()
+        }
+    }
+
+    // Bar and percentage when the size is known, a spinner when the server doesn't say
+    fn progress_line(&self, lead: &str, pad: &str, width: usize) -> String {
+        let theme = self.theme;
+        let progress = if self.known {
+            format!(
+                "{}  {}{}  {}  {} {} {}  {}  {} {}",
+                theme.colored_key(&format!("bar:{}", width)),
+                theme.label("{percent}"),
+                theme.accent("%"),
+                theme.accent(&theme.separator),
+                theme.label("{bytes}"),
+                theme.accent("/"),
+                theme.label("{total_bytes}"),
+                theme.accent(&theme.separator),
+                theme.label("ETA:"),
+                theme.accent("{eta}")
+            )
+        } else {
+            format!(
+                "{}  {}  {}",
+                theme.colored_key("spinner"),
+                theme.accent(&theme.separator),
+                theme.label("{bytes}")
+            )
+        };
+        format!("{}{}{}{}", lead, theme.label("Progress:"), pad, progress)
+    }
+
+    fn speed_line(&self, lead: &str, pad: &str) -> String {
+        let theme = self.theme;
+        format!(
+            "{}{}{}{}  {}  {} {}  {}  {} {}",
+            lead,
+            theme.label("Speed:"),
+            pad,
+            theme.accent("{bytes_per_sec}"),
+            theme.accent(&theme.separator),
+            theme.label("Avg:"),
+            theme.accent("{avg_speed}"),
+            theme.accent(&theme.separator),
+            theme.label("Peak:"),
+            theme.accent("{peak_speed}")
+        )
+    }
+
+    fn elapsed_line(&self, lead: &str, pad: &str) -> String {
+        format!(
+            "{}{}{}{}",
+            lead,
+            self.theme.label("Elapsed:"),
+            pad,
+            self.theme.accent("{elapsed_precise}")
+        )
+    }
+
+    fn look(&self, term_width: u16) -> Look {
+        let theme = self.theme;
+        let width = term_width as usize;
+        let ellipsis = theme.ellipsis.as_str();
+        match theme.layout_for(term_width) {
+            Layout::Minimal => Look {
+                template: format!(
+                    "{{msg}}\n{}\n{}\n{}",
+                    self.progress_line("", " ", 40),
+                    self.speed_line("", " "),
+                    self.elapsed_line("", " ")
+                ),
+                message: format!(
+                    "{} {}\n{} {}",
+                    theme.label("Downloading:"),
+                    theme.accent(&fit(&self.url, width.saturating_sub(13), ellipsis)),
+                    theme.label("File:"),
+                    theme.accent(&fit(&self.file_name, width.saturating_sub(6), ellipsis))
+                ),
+                finish: format!("{}", theme.label("Download complete!")),
+            },
+            Layout::Compact => {
+                let line = theme.label(&theme.horizontal.repeat(width));
+                let lead = self.lead("", true);
+                Look {
+                    template: format!(
+                        "{{msg}}\n{}\n{}\n{}\n{}",
+                        self.progress_line(&lead, "  ", 10),
+                        self.speed_line(&lead, "     "),
+                        self.elapsed_line(&lead, "   "),
+                        line
+                    ),
+                    message: format!(
+                        "{}\n{}{}         {}",
+                        line,
+                        lead,
+                        theme.label("File:"),
+                        theme.accent(&fit(&self.file_name, width.saturating_sub(17), ellipsis))
+                    ),
+                    finish: format!(
+                        "{}\n{} {}",
+                        line,
+                        theme.accent(&theme.bullet),
+                        theme.label("Download complete!")
+                    ),
+                }
+            }
+            Layout::Boxed | Layout::Auto => {
+                // The box grows with long URLs up to the terminal width, longer ones are cut
+                let url_length = self.url.chars().count();
+                let inner = (url_length + 15).max(94).min(width.saturating_sub(2));
+                let top = theme.label(&format!(
+                    "{}{}{}",
+                    theme.corner(0),
+                    theme.horizontal.repeat(inner),
+                    theme.corner(1)
+                ));
+                let bottom = theme.label(&format!(
+                    "{}{}{}",
+                    theme.corner(2),
+                    theme.horizontal.repeat(inner),
+                    theme.corner(3)
+                ));
+                let side = theme.label(&theme.vertical).to_string();
+                let lead = self.lead(&side, true);
+                Look {
+                    template: format!(
+                        "{{msg}}\n{}\n{}\n{}\n{}",
+                        self.progress_line(&lead, "  ", 40),
+                        self.speed_line(&lead, "     "),
+                        self.elapsed_line(&lead, "   "),
+                        bottom
+                    ),
+                    message: format!(
+                        "{}\n{}{} {}\n{}{}         {}",
+                        top,
+                        lead,
+                        theme.label("Downloading:"),
+                        theme.accent(&fit(&self.url, inner.saturating_sub(15), ellipsis)),
+                        lead,
+                        theme.label("File:"),
+                        theme.accent(&fit(&self.file_name, inner.saturating_sub(15), ellipsis))
+                    ),
+                    finish: format!(
+                        "{}\n{}{} {}",
+                        top,
+                        side,
+                        theme.accent(&theme.bullet),
+                        theme.label("Download complete!")
+                    ),
+                }
+            }
+        }
+    }
+
+    fn style(&self, look: &Look) -> ProgressStyle {
+        ProgressStyle::default_bar()
+            .template(&look.template)
+            .unwrap_or_else(|_| ProgressStyle::default_bar())
+            .progress_chars(&self.theme.bar)
+            .tick_chars(&self.theme.spinner)
+            .with_key("peak_speed", self.peak.clone())
+            .with_key(
+                "avg_speed",
+                |state: &ProgressState, w: &mut dyn fmt::Write| {
+                    let speed = average(state.pos(), state.elapsed());
+                    let _ = write!(w, "{}/s", HumanBytes(speed));
+                },
+            )
+    }
+
+    fn apply(&self, pb: &ProgressBar, term_width: u16) {
+        let look = self.look(term_width);
+        pb.set_style(self.style(&look));
+        pb.set_message(look.message);
+    }
+}
+
+// The progress bar of a download, laid out again whenever the terminal is resized
+pub struct Progress {
+    pb: ProgressBar,
+    display: Display,
+    done: Arc<AtomicBool>,
+    watcher: Option<JoinHandle<()>>,
+}
+
+impl Progress {
+    pub fn new(
+        theme: &'static Theme,
+        url: &str,
+        file_name: &str,
+        size: Option<u64>,
+        hidden: bool,
+    ) -> Progress {
+        let pb = match size {
+            Some(size) => ProgressBar::new(size),
+            None => ProgressBar::no_length(),
+        };
+        if hidden {
+            pb.set_draw_target(ProgressDrawTarget::hidden());
+        }
+        let display = Display {
+            theme,
+            url: url.to_string(),
+            file_name: file_name.to_string(),
+            known: size.is_some(),
+            peak: Peak::default(),
+        };
+        display.apply(&pb, term_width());
+        // Without a length the spinner keeps turning even while no data comes in
+        if !hidden && size.is_none() {
+            pb.enable_steady_tick(Duration::from_millis(120));
+        }
+        let done = Arc::new(AtomicBool::new(false));
+        let watcher = (!hidden && !pb.is_hidden())
+            .then(|| watch_resize(pb.clone(), display.clone(), done.clone()));
+        Progress {
+            pb,
+            display,
+            done,
+            watcher,
+        }
+    }
+
+    pub fn bar(&self) -> &ProgressBar {
+        &self.pb
+    }
+
+    fn stop(&mut self) {
+        self.done.store(true, Ordering::Relaxed);
+        if let Some(watcher) = self.watcher.take() {
+            let _ = watcher.join();
+        }
+    }
+
+    // "Download complete!" with how much came in, how long it took and how fast it was
+    pub fn finish(mut self) {
+        self.stop();
+        let theme = self.display.theme;
+        let look = self.display.look(term_width());
+        let bytes = self.pb.position();
+        let elapsed = self.pb.elapsed();
+        let summary = format!(
+            "{} in {}, avg {}/s, peak {}/s",
+            HumanBytes(bytes),
+            HumanDuration(elapsed),
+            HumanBytes(average(bytes, elapsed)),
+            HumanBytes(self.display.peak.get().max(average(bytes, elapsed)))
+        );
+        self.pb.set_style(self.display.style(&look));
+        self.pb
+            .finish_with_message(format!("{}  {}", look.finish, theme.accent(&summary)));
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// SIGWINCH tells about resizes on unix, the handler only sets a flag the watcher picks up
+#[cfg(unix)]
+fn watch_resize(pb: ProgressBar, display: Display, done: Arc<AtomicBool>) -> JoinHandle<()> {
+    let resized = Arc::new(AtomicBool::new(false));
+    let signal = signal_hook::flag::register(signal_hook::consts::SIGWINCH, resized.clone()).ok();
+    thread::spawn(move || {
+        while !done.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(50));
+            if resized.swap(false, Ordering::Relaxed) {
+                display.apply(&pb, term_width());
+                pb.tick();
+            }
+        }
+        if let Some(signal) = signal {
+            signal_hook::low_level::unregister(signal);
+        }
+    })
+}
+
+// Windows has no resize signal, the width is polled instead
+#[cfg(windows)]
+fn watch_resize(pb: ProgressBar, display: Display, done: Arc<AtomicBool>) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut width = term_width();
+        while !done.load(Ordering::Relaxed) {
+            thread::sleep(Duration::from_millis(200));
+            if term_width() != width {
+                width = term_width();
+                display.apply(&pb, width);
+                pb.tick();
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_cuts_the_middle() {
+        assert_eq!(fit("short", 10, "..."), "short");
+        assert_eq!(fit("abcdefghij", 7, "..."), "ab...ij");
+        assert_eq!(fit("abcdefghij", 8, "…"), "abcd…hij");
+    }
+
+    #[test]
+    fn fit_never_gets_longer_than_max() {
+        assert_eq!(fit("abcdefghij", 3, "..."), "...");
+        assert_eq!(fit("abcdefghij", 2, "..."), "..");
+        assert_eq!(fit("abcdefghij", 0, "..."), "");
+    }
+
+    #[test]
+    fn fit_counts_characters_not_bytes() {
+        assert_eq!(fit("žluťoučký kůň", 9, "…"), "žluť… kůň");
+        assert_eq!(fit("日本語のファイル.txt", 8, "…"), "日本語の…txt");
+    }
+}
@@ -0,0 +1,332 @@
+use crate::deps::{fetch_artifact, is_too_old, local_path, verify_download};
+use colored::*;
+use reqwest::blocking::Client;
+use reqwest::header::USER_AGENT;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+#[cfg(unix)]
+use std::fs::Permissions;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::process::Command;
+
+const LATEST_RELEASE: &str = "https://api.github.com/repos/mistrmochov/pls/releases/latest";
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+impl Release {
+    fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.iter().find(|asset| asset.name == name)
+    }
+}
+
+// What an update attempt came to, the reasons are printed along the way
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    UpToDate,
+    Updated,
+    Failed,
+}
+
+fn latest_release(feed: &str) -> Result<Release, Box<dyn Error>> {
+    let text = match local_path(feed) {
+        Some(path) => fs::read_to_string(path)?,
+        None => Client::new()
+            .get(feed)
+            .header(USER_AGENT, "pls")
+            .send()?
+            .error_for_status()?
+            .text()?,
+    };
+    Ok(serde_json::from_str(&text)?)
+}
+
+// Release binaries are named after the platform, e.g. pls-linux-x86_64 or pls-windows-x86_64.exe
+fn asset_name() -> String {
+    format!(
+        "pls-{}-{}{}",
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        std::env::consts::EXE_SUFFIX
+    )
+}
+
+fn runs(bin: &Path) -> bool {
+    Command::new(bin)
+        .arg("--version")
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+// The new binary is renamed over the running one, so it is never seen half written
+#[cfg(unix)]
+fn replace_exe(new: &Path, exe: &Path) -> io::Result<()> {
+    fs::rename(new, exe)
+}
+
+// Windows keeps the running executable open, it can be moved out of the way but not replaced
+#[cfg(windows)]
+fn replace_exe(new: &Path, exe: &Path) -> io::Result<()> {
+    let old = exe.with_extension("old.exe");
+    if old.exists() {
+        fs::remove_file(&old)?;
+    }
+    fs::rename(exe, &old)?;
+    if let Err(e) = fs::rename(new, exe) {
+        fs::rename(&old, exe)?;
+        return Err(e);
+    }
+    Ok(())
+}
+
+// Release tags may carry a v in front, e.g. v0.2.0
+fn newer_release(current: &str, tag: &str) -> bool {
+    is_too_old(current, tag.trim_start_matches('v'))
+}
+
+// PLS_RELEASES_URL swaps the GitHub release feed for another URL or a local JSON file
+pub fn self_update() -> io::Result<()> {
+    let feed = std::env::var("PLS_RELEASES_URL").unwrap_or_else(|_| LATEST_RELEASE.to_string());
+    update(env!("CARGO_PKG_VERSION"), &std::env::current_exe()?, &feed)?;
+    Ok(())
+}
+
+// Replaces exe with the release from the feed when it's newer than current
+fn update(current: &str, exe: &Path, feed: &str) -> io::Result<Outcome> {
+    let release = match latest_release(feed) {
+        Ok(release) => release,
+        Err(e) => {
+            println!(
+                "{} {} {}",
+                "Error:".red().bold(),
+                "Failed to look up the latest pls release:".white(),
+                e.to_string().white()
+            );
+            return Ok(Outcome::Failed);
+        }
+    };
+    let latest = release.tag_name.trim_start_matches('v');
+    if !newer_release(current, &release.tag_name) {
+        println!(
+            "{} {} {}",
+            "pls".blue().bold(),
+            current.white().bold(),
+            "is up to date.".white()
+        );
+        return Ok(Outcome::UpToDate);
+    }
+
+    let name = asset_name();
+    let Some(asset) = release.asset(&name) else {
+        println!(
+            "{} {} {} {}",
+            "Error:".red().bold(),
+            format!("Release {} has no", release.tag_name).white(),
+            name.white().bold(),
+            "binary, update pls by downloading the release yourself.".white()
+        );
+        return Ok(Outcome::Failed);
+    };
+    // A binary that replaces pls itself is never installed unverified
+    let Some(checksums) = release
+        .asset("SHA256SUMS")
+        .or_else(|| release.asset(&format!("{}.sha256", name)))
+    else {
+        println!(
+            "{} {} {}",
+            "Error:".red().bold(),
+            format!("Release {} publishes no checksums for", release.tag_name).white(),
+            name.white().bold()
+        );
+        return Ok(Outcome::Failed);
+    };
+
+    let Some(exe_dir) = exe.parent() else {
+        return Ok(Outcome::Failed);
+    };
+    // The download lands next to the executable so the final rename stays on one file system
+    let scratch = match tempfile::Builder::new()
+        .prefix(".pls-update-")
+        .tempdir_in(exe_dir)
+    {
+        Ok(scratch) => scratch,
+        Err(e) => {
+            println!(
+                "{} {} {}",
+                "Error:".red().bold(),
+                format!("Can't write to {},", exe_dir.to_string_lossy()).white(),
+                e.to_string().white()
+            );
+            return Ok(Outcome::Failed);
+        }
+    };
+    println!(
+        "{} {} {} {}",
+        "Updating".white(),
+        "pls".blue().bold(),
+        current.white(),
+        format!("-> {}", latest).white().bold()
+    );
+    let download = scratch.path().join(&name);
+    fetch_artifact(&asset.browser_download_url, &download)?;
+    if verify_download(
+        &asset.browser_download_url,
+        &download,
+        Some(&checksums.browser_download_url),
+    )?
+    .is_none()
+    {
+        return Ok(Outcome::Failed);
+    }
+    #[cfg(unix)]
+    fs::set_permissions(&download, Permissions::from_mode(0o755))?;
+    if !runs(&download) {
+        println!(
+            "{} {}",
+            "Error:".red().bold(),
+            "The downloaded pls doesn't run, keeping this version.".white()
+        );
+        return Ok(Outcome::Failed);
+    }
+
+    replace_exe(&download, exe)?;
+    println!(
+        "{} {} {}",
+        "pls".blue().bold(),
+        "has been successfully updated to".white(),
+        latest.white().bold()
+    );
+    Ok(Outcome::Updated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve;
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+    use std::path::PathBuf;
+    use tempfile::{tempdir, TempDir};
+
+    const OLD_EXE: &[u8] = b"#!/bin/sh\necho old\n";
+    const NEW_EXE: &[u8] = b"#!/bin/sh\necho pls 0.2.0\n";
+
+    fn sha256(data: &[u8]) -> String {
+        Sha256::digest(data)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+
+    // A release feed file listing the given assets, and the executable it may replace
+    fn setup(tag: &str, assets: &[(&str, String)]) -> (TempDir, PathBuf, String) {
+        let dir = tempdir().unwrap();
+        let assets: Vec<_> = assets
+            .iter()
+            .map(|(name, url)| json!({ "name": name, "browser_download_url": url }))
+            .collect();
+        let feed = dir.path().join("latest.json");
+        fs::write(
+            &feed,
+            json!({ "tag_name": tag, "assets": assets }).to_string(),
+        )
+        .unwrap();
+        let exe = dir.path().join("pls");
+        fs::write(&exe, OLD_EXE).unwrap();
+        (dir, exe, feed.to_string_lossy().to_string())
+    }
+
+    #[test]
+    fn versions_compare_numerically() {
+        assert!(newer_release("0.1.8", "v0.1.9"));
+        assert!(newer_release("0.1.8", "v0.10.0"));
+        assert!(newer_release("0.1.8", "0.2.0"));
+        assert!(!newer_release("0.1.8", "v0.1.8"));
+        assert!(!newer_release("0.2.0", "v0.1.9"));
+    }
+
+    #[test]
+    fn up_to_date_leaves_the_exe_alone() {
+        let (_dir, exe, feed) = setup("v0.1.8", &[]);
+        assert_eq!(update("0.1.8", &exe, &feed).unwrap(), Outcome::UpToDate);
+        assert_eq!(fs::read(&exe).unwrap(), OLD_EXE);
+    }
+
+    #[test]
+    fn missing_platform_asset_fails() {
+        let base = serve(vec![("/pls-plan9-mips", 200, NEW_EXE.to_vec())]);
+        let (_dir, exe, feed) = setup(
+            "v0.2.0",
+            &[("pls-plan9-mips", format!("{}/pls-plan9-mips", base))],
+        );
+        assert_eq!(update("0.1.8", &exe, &feed).unwrap(), Outcome::Failed);
+        assert_eq!(fs::read(&exe).unwrap(), OLD_EXE);
+    }
+
+    #[test]
+    fn missing_checksums_are_refused() {
+        let name = asset_name();
+        let path = format!("/{}", name);
+        let base = serve(vec![(path.as_str(), 200, NEW_EXE.to_vec())]);
+        let (_dir, exe, feed) = setup("v0.2.0", &[(&name, format!("{}{}", base, path))]);
+        assert_eq!(update("0.1.8", &exe, &feed).unwrap(), Outcome::Failed);
+        assert_eq!(fs::read(&exe).unwrap(), OLD_EXE);
+    }
+
+    #[test]
+    fn checksum_mismatch_keeps_the_running_exe() {
+        let name = asset_name();
+        let path = format!("/{}", name);
+        let sums = format!("{}  {}\n", sha256(b"another build"), name);
+        let base = serve(vec![
+            (path.as_str(), 200, NEW_EXE.to_vec()),
+            ("/SHA256SUMS", 200, sums.into_bytes()),
+        ]);
+        let (dir, exe, feed) = setup(
+            "v0.2.0",
+            &[
+                (&name, format!("{}{}", base, path)),
+                ("SHA256SUMS", format!("{}/SHA256SUMS", base)),
+            ],
+        );
+        assert_eq!(update("0.1.8", &exe, &feed).unwrap(), Outcome::Failed);
+        assert_eq!(fs::read(&exe).unwrap(), OLD_EXE);
+        // The scratch directory next to the exe is cleaned up
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn verified_release_replaces_the_exe() {
+        let name = asset_name();
+        let path = format!("/{}", name);
+        let sums = format!("{}  {}\n", sha256(NEW_EXE), name);
+        let base = serve(vec![
+            (path.as_str(), 200, NEW_EXE.to_vec()),
+            ("/SHA256SUMS", 200, sums.into_bytes()),
+        ]);
+        let (dir, exe, feed) = setup(
+            "v0.2.0",
+            &[
+                (&name, format!("{}{}", base, path)),
+                ("SHA256SUMS", format!("{}/SHA256SUMS", base)),
+            ],
+        );
+        assert_eq!(update("0.1.8", &exe, &feed).unwrap(), Outcome::Updated);
+        assert_eq!(fs::read(&exe).unwrap(), NEW_EXE);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 2);
+    }
+}
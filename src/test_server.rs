@@ -0,0 +1,45 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::thread;
+
+// Stands in for release hosts and mirrors in tests: every route is a path, a status and a
+// body, anything else is a 404. The server runs until the test process ends.
+pub fn serve(routes: Vec<(&str, u16, Vec<u8>)>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let base = format!("http://{}", listener.local_addr().unwrap());
+    let routes: Vec<(String, u16, Vec<u8>)> = routes
+        .into_iter()
+        .map(|(path, status, body)| (path.to_string(), status, body))
+        .collect();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else {
+                continue;
+            };
+            let mut reader = BufReader::new(&stream);
+            let mut request = String::new();
+            if reader.read_line(&mut request).is_err() {
+                continue;
+            }
+            // The headers aren't needed, but have to be read before answering
+            let mut line = String::new();
+            while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+                line.clear();
+            }
+            let path = request.split_whitespace().nth(1).unwrap_or_default();
+            let (status, body) = routes
+                .iter()
+                .find(|(route, _, _)| route == path)
+                .map(|(_, status, body)| (*status, body.clone()))
+                .unwrap_or((404, b"not found".to_vec()));
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {} Test\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            let _ = stream.write_all(&body);
+        }
+    });
+    base
+}
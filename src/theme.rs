@@ -0,0 +1,271 @@
+use colored::*;
+use serde::Deserialize;
+use std::io::{self, IsTerminal};
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Layout {
+    // boxed on wide terminals, compact on narrow ones
+    Auto,
+    Boxed,
+    Compact,
+    Minimal,
+}
+
+// How the download progress looks, a preset with the [theme] table of config.toml on top
+#[derive(Clone)]
+pub struct Theme {
+    pub layout: Layout,
+    // Terminals up to this many columns get the compact layout in auto mode
+    pub compact_width: u16,
+    pub colors: bool,
+    pub label: [u8; 3],
+    pub accent: [u8; 3],
+    pub arrow: String,
+    pub bullet: String,
+    pub separator: String,
+    pub horizontal: String,
+    pub vertical: String,
+    // Top left, top right, bottom left and bottom right
+    pub corners: String,
+    pub bar: String,
+    pub bar_color: String,
+    // Spinner frames for downloads of unknown size, the last one is shown when done
+    pub spinner: String,
+    // Cuts long URLs and file names short in the middle
+    pub ellipsis: String,
+}
+
+const PRESETS: [&str; 4] = ["default", "ascii", "mono", "minimal"];
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            layout: Layout::Auto,
+            compact_width: 100,
+            colors: true,
+            label: [255, 180, 215],
+            accent: [150, 200, 255],
+            arrow: "⟶".to_string(),
+            bullet: "●".to_string(),
+            separator: "|".to_string(),
+            horizontal: "─".to_string(),
+            vertical: "│".to_string(),
+            corners: "┌┐└┘".to_string(),
+            bar: "█▉▊▋▌▍▎▏  ".to_string(),
+            bar_color: "blue".to_string(),
+            spinner: "⠁⠂⠄⡀⢀⠠⠐⠈ ".to_string(),
+            ellipsis: "…".to_string(),
+        }
+    }
+}
+
+fn preset(name: &str) -> Option<Theme> {
+    let default = Theme::default();
+    match name {
+        "default" => Some(default),
+        // For terminals and fonts without box drawing characters
+        "ascii" => Some(Theme {
+            arrow: "->".to_string(),
+            bullet: "*".to_string(),
+            horizontal: "-".to_string(),
+            vertical: "|".to_string(),
+            corners: "++++".to_string(),
+            bar: "#>-".to_string(),
+            spinner: "|/-\\ ".to_string(),
+            ellipsis: "...".to_string(),
+            ..default
+        }),
+        "mono" => Some(Theme {
+            colors: false,
+            ..default
+        }),
+        "minimal" => Some(Theme {
+            layout: Layout::Minimal,
+            ..default
+        }),
+        _ => None,
+    }
+}
+
+// Logs and pipes get plain ASCII lines without colours
+pub fn plain() -> Theme {
+    Theme {
+        layout: Layout::Minimal,
+        colors: false,
+        arrow: "->".to_string(),
+        bullet: "*".to_string(),
+        bar: "#>-".to_string(),
+        spinner: "|/-\\ ".to_string(),
+        ellipsis: "...".to_string(),
+        ..Theme::default()
+    }
+}
+
+// The [theme] table of config.toml, the fields that are set replace the preset's
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ThemeFile {
+    preset: Option<String>,
+    layout: Option<Layout>,
+    compact_width: Option<u16>,
+    colors: Option<bool>,
+    label: Option<[u8; 3]>,
+    accent: Option<[u8; 3]>,
+    arrow: Option<String>,
+    bullet: Option<String>,
+    separator: Option<String>,
+    horizontal: Option<String>,
+    vertical: Option<String>,
+    corners: Option<String>,
+    bar: Option<String>,
+    bar_color: Option<String>,
+    spinner: Option<String>,
+    ellipsis: Option<String>,
+}
+
+fn preset_or_error(name: &str) -> Result<Theme, String> {
+    preset(name).ok_or_else(|| {
+        format!(
+            "Unknown theme {}, the presets are {}",
+            name,
+            PRESETS.join(", ")
+        )
+    })
+}
+
+// A theme named on the command line wins over the whole [theme] table
+pub fn load(name: Option<&str>, file: ThemeFile) -> Result<Theme, String> {
+    if let Some(name) = name {
+        return preset_or_error(name);
+    }
+    let mut theme = preset_or_error(file.preset.as_deref().unwrap_or("default"))?;
+    theme.layout = file.layout.unwrap_or(theme.layout);
+    theme.compact_width = file.compact_width.unwrap_or(theme.compact_width);
+    theme.colors = file.colors.unwrap_or(theme.colors);
+    theme.label = file.label.unwrap_or(theme.label);
+    theme.accent = file.accent.unwrap_or(theme.accent);
+    theme.arrow = file.arrow.unwrap_or(theme.arrow);
+    theme.bullet = file.bullet.unwrap_or(theme.bullet);
+    theme.separator = file.separator.unwrap_or(theme.separator);
+    theme.horizontal = file.horizontal.unwrap_or(theme.horizontal);
+    theme.vertical = file.vertical.unwrap_or(theme.vertical);
+    theme.corners = file.corners.unwrap_or(theme.corners);
+    theme.bar = file.bar.unwrap_or(theme.bar);
+    theme.bar_color = file.bar_color.unwrap_or(theme.bar_color);
+    theme.spinner = file.spinner.unwrap_or(theme.spinner);
+    theme.ellipsis = file.ellipsis.unwrap_or(theme.ellipsis);
+
+    if theme.corners.chars().count() != 4 {
+        return Err("theme corners needs exactly 4 characters".to_string());
+    }
+    // indicatif needs a full and an empty character at least
+    if theme.bar.chars().count() < 2 {
+        return Err("theme bar needs at least 2 characters".to_string());
+    }
+    if theme.spinner.chars().count() < 2 {
+        return Err("theme spinner needs at least 2 characters".to_string());
+    }
+    if theme.horizontal.chars().count() != 1 {
+        return Err("theme horizontal needs exactly 1 character".to_string());
+    }
+    Ok(theme)
+}
+
+impl Theme {
+    pub fn label(&self, text: &str) -> ColoredString {
+        if self.colors {
+            text.truecolor(self.label[0], self.label[1], self.label[2])
+        } else {
+            text.normal()
+        }
+    }
+
+    pub fn accent(&self, text: &str) -> ColoredString {
+        if self.colors {
+            text.truecolor(self.accent[0], self.accent[1], self.accent[2])
+        } else {
+            text.normal()
+        }
+    }
+
+    pub fn corner(&self, index: usize) -> String {
+        self.corners
+            .chars()
+            .nth(index)
+            .map(String::from)
+            .unwrap_or_default()
+    }
+
+    pub fn colored_key(&self, key: &str) -> String {
+        if self.colors && !self.bar_color.is_empty() {
+            format!("{{{}.{}}}", key, self.bar_color)
+        } else {
+            format!("{{{}}}", key)
+        }
+    }
+
+    pub fn layout_for(&self, term_width: u16) -> Layout {
+        match self.layout {
+            Layout::Auto if term_width <= self.compact_width => Layout::Compact,
+            Layout::Auto => Layout::Boxed,
+            layout => layout,
+        }
+    }
+
+    // Status lines like "File saved to: ..." after the bar, which leaves the cursor on its last
+    // line when both share a terminal
+    pub fn done(&self, label: &str, value: &str) {
+        if self.layout == Layout::Minimal {
+            let gap = if io::stdout().is_terminal() { "\n" } else { "" };
+            println!("{}{} {}", gap, self.label(label), self.accent(value));
+        } else {
+            println!(
+                "\n {} {} {}",
+                self.accent(&self.bullet),
+                self.label(label),
+                self.accent(value)
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_toml(text: &str) -> Result<Theme, String> {
+        load(None, toml::from_str(text).unwrap())
+    }
+
+    fn error(text: &str) -> String {
+        load_toml(text).err().unwrap_or_default()
+    }
+
+    #[test]
+    fn glyph_counts_are_checked() {
+        assert!(load_toml("corners = '╭╮╰╯'").is_ok());
+        assert!(error("corners = '+++'").contains("corners"));
+        assert!(error("corners = '+++++'").contains("corners"));
+
+        assert!(load_toml("bar = '#-'").is_ok());
+        assert!(error("bar = '#'").contains("bar"));
+
+        assert!(load_toml("spinner = '|/-\\'").is_ok());
+        assert!(error("spinner = '|'").contains("spinner"));
+
+        assert!(error("horizontal = '──'").contains("horizontal"));
+    }
+
+    #[test]
+    fn fields_replace_the_preset() {
+        let ascii = preset("ascii").unwrap();
+        let theme = load_toml("preset = 'ascii'\nbar = '=>-'").unwrap();
+        assert_eq!(theme.bar, "=>-");
+        assert_eq!(theme.corners, ascii.corners);
+        assert!(error("preset = 'neon'").contains("Unknown theme neon"));
+        // A theme named on the command line ignores the table
+        let theme = load(Some("ascii"), toml::from_str("bar = '#'").unwrap()).unwrap();
+        assert_eq!(theme.bar, ascii.bar);
+    }
+}